@@ -4,14 +4,17 @@ use serenity::prelude::*;
 
 use super::super::start_bot::LogAnalysisClientContainer;
 use crate::fight_analysis::analyse_fight::{
-    analyse_fights_by_name, convert_report_code, get_report_stats, summarise_report, AnalysisError,
+    analyse_fights_by_name_with_progress, convert_report_code, get_report_stats, summarise_report,
+    AnalysisError,
 };
 
 use futures::select;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time;
+use tokio::sync::Mutex;
 use tokio::time::delay_for;
 
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 
 #[command]
 #[description = "Gets statistics for progression on a specified fight in the provided FFLogs report"]
@@ -33,13 +36,26 @@ pub async fn fight_stats(ctx: &Context, msg: &Message, mut args: Args) -> Comman
         ))?;
 
     let report_code: String = handle_errors(ctx, msg, convert_report_code(tgt_report)).await?;
+    let progress: AnalysisProgress = Default::default();
     let analysis_fut = select! {
-        analysis = analyse_fights_by_name(report_code.to_string(), fight_name, analysis_client).fuse() => Some(analysis),
-        _ = show_typing(ctx, msg).fuse() => None,
+        analysis = analyse_fights_by_name_with_progress(
+            report_code.to_string(),
+            fight_name,
+            analysis_client,
+            |done, total| progress.update(done, total),
+        ).fuse() => Some(analysis),
+        _ = show_progress(ctx, msg, &progress).fuse() => None,
     }
     .ok_or(CommandError(
         "Something went horribly wrong. Discord API calls failed.".to_string(),
     ))?;
+    //The results are still worth posting if the progress message couldn't be updated
+    if let Err(e) = progress.show_final_count(ctx).await {
+        warn!(
+            "Failed to update progress message with final count: {:?}",
+            e
+        );
+    }
 
     let analysis = handle_errors(ctx, msg, analysis_fut).await;
     debug!(
@@ -64,10 +80,58 @@ pub async fn fight_stats(ctx: &Context, msg: &Message, mut args: Args) -> Comman
     Ok(())
 }
 
-async fn show_typing(ctx: &Context, msg: &Message) -> CommandResult {
+/// Number of pulls analysed so far, shared between the analysis and the progress message
+#[derive(Default)]
+struct AnalysisProgress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+    status_msg: Mutex<Option<Message>>,
+}
+
+impl AnalysisProgress {
+    fn update(&self, done: usize, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.done.store(done, Ordering::Relaxed);
+    }
+
+    fn status_text(&self) -> String {
+        return format!(
+            "Analysed {}/{} pulls",
+            self.done.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed)
+        );
+    }
+
+    /// Brings the progress message up to date once the analysis has finished, as it
+    /// is otherwise only updated every few seconds
+    async fn show_final_count(&self, ctx: &Context) -> CommandResult {
+        if let Some(status) = self.status_msg.lock().await.as_mut() {
+            let content = self.status_text();
+            status.edit(ctx, |m| m.content(&content)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the typing indicator going whilst a report is analysed, along with a message
+/// showing how many pulls have been analysed so far
+async fn show_progress(ctx: &Context, msg: &Message, progress: &AnalysisProgress) -> CommandResult {
+    let mut last_done = 0;
     loop {
         msg.channel_id.broadcast_typing(ctx).await?;
         info!("Sent typing notification to discord.");
+        let done = progress.done.load(Ordering::Relaxed);
+        let total = progress.total.load(Ordering::Relaxed);
+        //Only worth reporting progress for reports with several pulls
+        if total > 1 && done != last_done {
+            let content = progress.status_text();
+            let mut status_msg = progress.status_msg.lock().await;
+            match status_msg.as_mut() {
+                Some(status) => status.edit(ctx, |m| m.content(&content)).await?,
+                None => *status_msg = Some(msg.channel_id.say(ctx, &content).await?),
+            }
+            last_done = done;
+        }
         delay_for(time::Duration::from_secs(4)).await;
     }
 }
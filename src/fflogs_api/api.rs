@@ -1,71 +1,393 @@
 use hyper::body::Buf;
-use hyper::{Body, Client, Method, Request};
-use hyper_tls::HttpsConnector;
+use hyper::header::RETRY_AFTER;
+use hyper::{Body, Method, Request, Response, StatusCode};
 
 use http::uri::{Authority, Builder, PathAndQuery, Scheme, Uri};
 
 use serde::Serialize;
 
+use super::cache::{cache_key, CacheConfig, ResponseCache};
+use super::fixtures::{FixtureConfig, FixtureMode, RecordingTransport, ReplayTransport};
+use super::rate_limit::{RateLimitConfig, RateLimiter};
+use super::transport::{HttpTransport, HyperTransport};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use std::cmp::min;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::spawn_blocking;
+use tokio::time::delay_for;
+
 use log::{info, warn};
 
 pub const FFLOGS_SCHEME: &'static str = "https";
 pub const FFLOGS_AUTHORITY: &'static str = "www.fflogs.com:443";
 
 pub struct FFLogsApiClient {
-    hyper_client: Client<HttpsConnector<hyper::client::connect::HttpConnector>, Body>,
+    transport: Box<dyn HttpTransport>,
     api_key: String,
+    config: ApiClientConfig,
+    rate_limiter: RateLimiter,
+    cache: Option<Arc<ResponseCache>>,
 }
 
 impl FFLogsApiClient {
+    /// Runs a GET request against the given uri, retrying with exponential backoff
+    /// if the API throttles us or fails with a server error.
     pub async fn run_request(&self, uri: Uri) -> Result<String, ApiError> {
         let target_uri = uri.to_string();
-        let request = Request::builder()
-            .method(Method::GET)
-            .uri(uri)
-            .header("content-type", "application/json")
-            .body(Body::empty())
-            .map_err(|err| ApiError::RequestConstructionError(err))?;
+        return self
+            .run_with_retry(&target_uri, || {
+                Request::builder()
+                    .method(Method::GET)
+                    .uri(uri.clone())
+                    .header("content-type", "application/json")
+                    .body(Body::empty())
+            })
+            .await;
+    }
+
+    /// Runs a POST request with the given body against the given uri, with the same
+    /// retry behaviour as `run_request`.
+    pub async fn run_post_request(
+        &self,
+        uri: Uri,
+        content_type: &str,
+        authorization: Option<&str>,
+        body: String,
+    ) -> Result<String, ApiError> {
+        let target_uri = uri.to_string();
+        return self
+            .run_with_retry(&target_uri, || {
+                let mut builder = Request::builder()
+                    .method(Method::POST)
+                    .uri(uri.clone())
+                    .header("content-type", content_type);
+                if let Some(auth) = authorization {
+                    builder = builder.header("authorization", auth);
+                }
+                builder.body(Body::from(body.clone()))
+            })
+            .await;
+    }
+
+    async fn run_with_retry<F>(
+        &self,
+        target_uri: &str,
+        build_request: F,
+    ) -> Result<String, ApiError>
+    where
+        F: Fn() -> Result<Request<Body>, http::Error>,
+    {
+        let retry = &self.config.retry;
+        let mut attempt: u32 = 0;
+        loop {
+            attempt += 1;
+            let request = build_request().map_err(|err| ApiError::RequestConstructionError(err))?;
+            let (err, retry_after) = match self.run_single_request(request).await {
+                Ok(body) => {
+                    info!("Successfully requested data from endpoint {}", target_uri);
+                    return Ok(body);
+                }
+                Err(RequestFailure::Fatal(err)) => return Err(err),
+                Err(RequestFailure::Retryable(err, retry_after)) => (err, retry_after),
+            };
+            if attempt >= retry.max_attempts {
+                warn!(
+                    "Giving up on request to {} after {} attempts, last error was {:?}",
+                    target_uri, attempt, err
+                );
+                return Err(ApiError::RetriesExhausted((attempt, Box::new(err))));
+            }
+            let delay = retry_after
+                .map(|d| min(d, Duration::from_millis(retry.max_delay_ms)))
+                .unwrap_or_else(|| retry.backoff_delay(attempt));
+            warn!(
+                "Request to {} failed on attempt {} with error {:?}, retrying in {}ms",
+                target_uri,
+                attempt,
+                err,
+                delay.as_millis()
+            );
+            delay_for(delay).await;
+        }
+    }
+
+    /// Runs a request for data belonging to a single report, serving it from the
+    /// response cache if one is configured and holds a fresh copy.
+    pub async fn run_report_request(
+        &self,
+        uri: Uri,
+        report_code: &str,
+    ) -> Result<String, ApiError> {
+        return self
+            .run_report_request_with_end(uri, report_code, |_| None)
+            .await;
+    }
+
+    /// As `run_report_request`, but for responses which say when the report ended.
+    /// `report_end` reads the end time out of a fresh response so that it is known
+    /// before the response is cached.
+    pub async fn run_report_request_with_end<F>(
+        &self,
+        uri: Uri,
+        report_code: &str,
+        report_end: F,
+    ) -> Result<String, ApiError>
+    where
+        F: FnOnce(&str) -> Option<u64>,
+    {
+        let cache = match &self.cache {
+            None => return self.run_request(uri).await,
+            Some(cache) => cache,
+        };
+        let key = cache_key(&uri);
+        //The cache reads and writes files, so keep it off the async executor
+        let lookup = {
+            let (cache, key) = (cache.clone(), key.clone());
+            spawn_blocking(move || cache.get(&key)).await
+        };
+        if let Ok(Some(body)) = lookup {
+            return Ok(body);
+        }
+        let body = self.run_request(uri).await?;
+        if let Some(end) = report_end(&body) {
+            cache.record_report_end(report_code, end);
+        }
+        let store = {
+            let (cache, report_code, body) = (cache.clone(), report_code.to_string(), body.clone());
+            spawn_blocking(move || cache.put(&key, &report_code, &body)).await
+        };
+        if let Err(e) = store {
+            warn!("Failed to store response in cache: {:?}", e);
+        }
+        return Ok(body);
+    }
+
+    /// Lets the response cache know when the last event in a report was logged, so
+    /// that it can tell whether the report is still in progress.
+    pub fn record_report_end(&self, report_code: &str, end_millis: u64) {
+        if let Some(cache) = &self.cache {
+            cache.record_report_end(report_code, end_millis);
+        }
+    }
+
+    async fn run_single_request(&self, request: Request<Body>) -> Result<String, RequestFailure> {
+        let target_uri = request.uri().to_string();
+        let _permit = self.rate_limiter.acquire().await;
         let res = self
-            .hyper_client
-            .request(request)
+            .transport
+            .send(request)
             .await
-            .map_err(|err| ApiError::RequestError(err))?;
+            .map_err(|err| match err {
+                ApiError::RequestError(ref e) if e.is_connect() => {
+                    RequestFailure::Retryable(err, None)
+                }
+                _ => RequestFailure::Fatal(err),
+            })?;
         if !res.status().is_success() {
-            let status = res.status().as_u16();
+            let status = res.status();
             warn!(
                 "Request to api endpoint found at {:?} return non-success response {}",
-                target_uri, status
+                target_uri,
+                status.as_u16()
             );
+            let retry_after = parse_retry_after(&res);
             let body = hyper::body::aggregate(res)
                 .await
-                .map_err(|err| ApiError::ApiReturnedError((status, err.to_string())))?
+                .map_err(|err| failure_for_status(status, err.to_string(), retry_after))?
                 .to_bytes();
             let body_str = String::from_utf8_lossy(&body);
-            return Err(ApiError::ApiReturnedError((status, body_str.to_string())));
+            return Err(failure_for_status(
+                status,
+                body_str.to_string(),
+                retry_after,
+            ));
         }
         let body = hyper::body::aggregate(res)
             .await
-            .map_err(|err| ApiError::RequestError(err))?
+            .map_err(|err| RequestFailure::Fatal(ApiError::RequestError(err)))?
             .to_bytes();
         let body_string = String::from_utf8_lossy(&body);
-        info!("Successfully requested data from endpoint {}", target_uri);
         return Ok(body_string.to_string());
     }
 
     pub fn api_key(&self) -> &str {
         return &self.api_key;
     }
+
+    /// Generates a Uri representing a given request to the API server this client is
+    /// configured to talk to
+    pub fn request_uri<T>(&self, path: &str, query: T) -> Result<Uri, ApiError>
+    where
+        T: Serialize,
+    {
+        to_uri(&self.config.scheme, &self.config.authority, path, query)
+    }
+}
+
+/// The outcome of a single failed attempt at a request, used to decide whether
+/// `run_request` should try again.
+enum RequestFailure {
+    Retryable(ApiError, Option<Duration>),
+    Fatal(ApiError),
+}
+
+/// Reads the `Retry-After` header from a response, which may contain either a
+/// number of seconds or an HTTP date.
+fn parse_retry_after(res: &Response<Body>) -> Option<Duration> {
+    let header = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = header.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(header).ok()?;
+    let wait = date.with_timezone(&Utc) - Utc::now();
+    return wait.to_std().ok().or(Some(Duration::from_secs(0)));
+}
+
+/// Classifies a non-success response, retrying on throttling and server errors.
+fn failure_for_status(
+    status: StatusCode,
+    message: String,
+    retry_after: Option<Duration>,
+) -> RequestFailure {
+    let err = ApiError::ApiReturnedError((status.as_u16(), message));
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        return RequestFailure::Retryable(err, retry_after);
+    } else {
+        return RequestFailure::Fatal(err);
+    }
+}
+
+/// Configuration options for an FFLogsApiClient
+#[derive(Debug, Clone)]
+pub struct ApiClientConfig {
+    /// Scheme used to reach the API server, either `https` or `http`
+    pub scheme: String,
+    /// Host (and optionally port) of the API server
+    pub authority: String,
+    pub retry: RetryConfig,
+    pub rate_limit: RateLimitConfig,
+    /// Where to cache report data on disk, or `None` to disable caching
+    pub cache: Option<CacheConfig>,
+    /// Record API traffic to, or replay it from, a fixture directory
+    pub fixtures: Option<FixtureConfig>,
+}
+
+impl std::default::Default for ApiClientConfig {
+    fn default() -> Self {
+        return ApiClientConfig {
+            scheme: FFLOGS_SCHEME.to_string(),
+            authority: FFLOGS_AUTHORITY.to_string(),
+            retry: Default::default(),
+            rate_limit: Default::default(),
+            cache: None,
+            fixtures: None,
+        };
+    }
+}
+
+/// Controls how failed requests are retried. Requests are only retried when the API
+/// responds with a 429 or 5xx status or the connection could not be established.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Total number of attempts made before giving up, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on each subsequent attempt
+    pub base_delay_ms: u64,
+    /// Upper bound on the delay between two attempts
+    pub max_delay_ms: u64,
+    /// Whether to randomise each delay between zero and the backoff value
+    pub jitter: bool,
+}
+
+impl RetryConfig {
+    /// Returns the time to wait after the given (1-indexed) failed attempt
+    pub fn backoff_delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .base_delay_ms
+            .saturating_mul(1u64 << exp)
+            .min(self.max_delay_ms);
+        let delay = if self.jitter && backoff > 0 {
+            rand::thread_rng().gen_range(0, backoff + 1)
+        } else {
+            backoff
+        };
+        return Duration::from_millis(delay);
+    }
+}
+
+impl std::default::Default for RetryConfig {
+    fn default() -> Self {
+        return RetryConfig {
+            max_attempts: 5,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+            jitter: true,
+        };
+    }
 }
 
 /// Creates a new FFLogsApiClient struct with the given API key and
 /// an HTTPS client
 pub fn new_fflogs_api_client(api_key: &str) -> FFLogsApiClient {
-    let https = HttpsConnector::new();
-    let hyper_client = Client::builder().build::<_, Body>(https);
+    return new_fflogs_api_client_with_config(api_key, Default::default());
+}
+
+/// Creates a new FFLogsApiClient struct with the given API key and configuration
+pub fn new_fflogs_api_client_with_config(
+    api_key: &str,
+    mut config: ApiClientConfig,
+) -> FFLogsApiClient {
+    let transport: Box<dyn HttpTransport> = match &config.fixtures {
+        None => Box::new(HyperTransport::new()),
+        Some(fixtures) => {
+            //Responses served from the cache would never reach the recorder
+            if config.cache.take().is_some() {
+                info!("Disabling response cache whilst using API fixtures");
+            }
+            match fixtures.mode {
+                FixtureMode::Replay => Box::new(ReplayTransport::new(&fixtures.directory)),
+                FixtureMode::Record => Box::new(
+                    RecordingTransport::new(Box::new(HyperTransport::new()), &fixtures.directory)
+                        .expect("Failed to create fixture directory"),
+                ),
+            }
+        }
+    };
+    return new_fflogs_api_client_with_transport(api_key, config, transport);
+}
+
+/// Creates a new FFLogsApiClient struct which sends its requests through the given
+/// transport rather than directly over the network
+pub fn new_fflogs_api_client_with_transport(
+    api_key: &str,
+    config: ApiClientConfig,
+    transport: Box<dyn HttpTransport>,
+) -> FFLogsApiClient {
+    let cache =
+        config
+            .cache
+            .clone()
+            .and_then(|cache_config| match ResponseCache::open(cache_config) {
+                Ok(cache) => Some(Arc::new(cache)),
+                Err(e) => {
+                    warn!(
+                        "Failed to open response cache, continuing without it: {:?}",
+                        e
+                    );
+                    None
+                }
+            });
 
     let new_client = FFLogsApiClient {
-        hyper_client: hyper_client,
+        transport: transport,
         api_key: api_key.to_owned(),
+        rate_limiter: RateLimiter::new(&config.rate_limit),
+        cache: cache,
+        config: config,
     };
     info!(
         "Created new FFLogs API Client with API key {}",
@@ -89,10 +411,24 @@ pub enum ApiError {
     RequestConstructionError(http::Error),
     RequestError(hyper::Error),
     ApiReturnedError((u16, String)),
+    RetriesExhausted((u32, Box<ApiError>)),
+    FixtureNotFound(String),
+    GraphQLError(Vec<String>),
     ResponseFormatError(serde_json::Error),
     NotImplementedError,
 }
 
+impl ApiError {
+    /// Returns true if this error was caused by the API rate limiting our requests
+    pub fn is_throttled(&self) -> bool {
+        match self {
+            ApiError::ApiReturnedError((status, _)) => *status == 429,
+            ApiError::RetriesExhausted((_, last)) => last.is_throttled(),
+            _ => false,
+        }
+    }
+}
+
 /// Given a path and a struct implementing ToQueryString returns a string
 /// conaining the path and query which can then be used to create a uri.
 pub fn to_path_and_query<T>(path: &str, query: T) -> Result<String, ApiError>
@@ -101,10 +437,31 @@ where
 {
     let query_string = serde_urlencoded::to_string(query);
     return query_string
-        .map(|qstr| format!("{}?{}", path, qstr))
+        .map(|qstr| {
+            if qstr.is_empty() {
+                path.to_string()
+            } else {
+                format!("{}?{}", path, qstr)
+            }
+        })
         .map_err(|err| ApiError::QueryStringGenerationError(err.to_string()));
 }
 
+/// Percent-encodes a value so that it can be used as a single segment of a path, e.g.
+/// a character or guild name containing spaces or apostrophes.
+pub fn encode_path_segment(segment: &str) -> String {
+    let mut res = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                res.push(byte as char)
+            }
+            _ => res.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    return res;
+}
+
 /// Given a scheme, base url, resource path and struct implementing ToQueryString,
 /// attempts to create a Uri obeject.
 pub fn to_uri<T>(scheme: &str, authority: &str, path: &str, query: T) -> Result<Uri, ApiError>
@@ -137,3 +494,131 @@ where
 {
     to_uri(FFLOGS_SCHEME, FFLOGS_AUTHORITY, path, query)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use std::sync::Mutex;
+    use tokio::runtime::Runtime;
+
+    /// Transport which replies to each request with the next of a list of canned
+    /// responses
+    struct ScriptedTransport {
+        responses: Mutex<Vec<(u16, &'static str)>>,
+    }
+
+    impl HttpTransport for ScriptedTransport {
+        fn send(&self, _: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+            let (status, body) = self.responses.lock().unwrap().remove(0);
+            let res = Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap();
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    fn scripted_client(responses: Vec<(u16, &'static str)>) -> FFLogsApiClient {
+        let mut config: ApiClientConfig = Default::default();
+        config.scheme = "http".to_string();
+        config.authority = "localhost:8080".to_string();
+        config.retry.base_delay_ms = 0;
+        config.retry.max_attempts = 3;
+        let transport = ScriptedTransport {
+            responses: Mutex::new(responses),
+        };
+        return new_fflogs_api_client_with_transport("key", config, Box::new(transport));
+    }
+
+    #[test]
+    fn test_run_request_retries_server_errors() {
+        let client = scripted_client(vec![(503, ""), (429, ""), (200, "ok")]);
+        let uri = client
+            .request_uri("/v1/test", &[("translate", "true")])
+            .unwrap();
+        assert_eq!(
+            uri.to_string(),
+            "http://localhost:8080/v1/test?translate=true"
+        );
+        let res = Runtime::new().unwrap().block_on(client.run_request(uri));
+        assert_eq!(res.unwrap(), "ok");
+    }
+
+    #[test]
+    fn test_run_request_gives_up_when_throttled() {
+        let client = scripted_client(vec![(429, ""), (429, ""), (429, "slow down")]);
+        let uri = client
+            .request_uri("/v1/test", &[("translate", "true")])
+            .unwrap();
+        let res = Runtime::new().unwrap().block_on(client.run_request(uri));
+        match res {
+            Err(err @ ApiError::RetriesExhausted((3, _))) => assert!(err.is_throttled()),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_run_request_does_not_retry_client_errors() {
+        let client = scripted_client(vec![(404, "not found"), (200, "ok")]);
+        let uri = client
+            .request_uri("/v1/test", &[("translate", "true")])
+            .unwrap();
+        let res = Runtime::new().unwrap().block_on(client.run_request(uri));
+        match res {
+            Err(ApiError::ApiReturnedError((404, _))) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    /// Transport which replies with a 503 whose body fails part way through, then
+    /// with a successful response
+    struct AbortedBodyTransport {
+        attempts: Mutex<usize>,
+    }
+
+    impl HttpTransport for AbortedBodyTransport {
+        fn send(&self, _: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+            let mut attempts = self.attempts.lock().unwrap();
+            *attempts += 1;
+            let res = if *attempts == 1 {
+                let (sender, body) = Body::channel();
+                sender.abort();
+                Response::builder().status(503).body(body).unwrap()
+            } else {
+                Response::builder()
+                    .status(200)
+                    .body(Body::from("ok"))
+                    .unwrap()
+            };
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_run_request_retries_unreadable_error_bodies() {
+        let mut config: ApiClientConfig = Default::default();
+        config.retry.base_delay_ms = 0;
+        let transport = AbortedBodyTransport {
+            attempts: Mutex::new(0),
+        };
+        let client = new_fflogs_api_client_with_transport("key", config, Box::new(transport));
+        let uri = client
+            .request_uri("/v1/test", &[("translate", "true")])
+            .unwrap();
+        let res = Runtime::new().unwrap().block_on(client.run_request(uri));
+        assert_eq!(res.unwrap(), "ok");
+    }
+
+    #[test]
+    fn test_backoff_delay_doubles_and_caps() {
+        let cfg = RetryConfig {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 1000,
+            jitter: false,
+        };
+        let delays: Vec<u128> = (1..=6).map(|a| cfg.backoff_delay(a).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+    }
+}
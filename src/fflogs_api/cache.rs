@@ -0,0 +1,441 @@
+//! A persistent on-disk cache for API responses. Each response is stored as a
+//! gzip-compressed JSON file named after a hash of the request uri (with the
+//! `api_key` parameter removed), and the total size of the cache is kept under a
+//! configurable cap by evicting the least recently used entries.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use http::uri::Uri;
+use serde::{Deserialize, Serialize};
+
+use log::{debug, info, warn};
+
+const CACHE_FILE_EXTENSION: &'static str = "json.gz";
+const TEMP_FILE_EXTENSION: &'static str = "tmp";
+
+/// Configuration for the on-disk response cache
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Directory in which cached responses are stored
+    pub directory: PathBuf,
+    /// Maximum total size of all cached responses on disk, in bytes
+    pub max_size_bytes: u64,
+    /// A report whose last recorded event is more recent than this is assumed to
+    /// still be being logged
+    pub live_report_threshold_secs: u64,
+    /// How long to keep responses for reports which are still being logged
+    pub live_report_ttl_secs: u64,
+    /// How long to keep responses for finished reports, or `None` to keep them until
+    /// they are evicted
+    pub finished_report_ttl_secs: Option<u64>,
+}
+
+impl CacheConfig {
+    pub fn new(directory: &str) -> CacheConfig {
+        return CacheConfig {
+            directory: PathBuf::from(directory),
+            max_size_bytes: 512 * 1024 * 1024,
+            live_report_threshold_secs: 60 * 60,
+            live_report_ttl_secs: 2 * 60,
+            finished_report_ttl_secs: None,
+        };
+    }
+}
+
+/// Returns the key under which the response to a given uri is cached. This is the
+/// path and query of the uri with the `api_key` parameter stripped, so that entries
+/// are not tied to (or leak) the key they were fetched with.
+pub fn cache_key(uri: &Uri) -> String {
+    let path = uri.path();
+    let query: Vec<&str> = uri
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|param| !param.is_empty() && !param.starts_with("api_key="))
+        .collect();
+    if query.is_empty() {
+        return path.to_string();
+    }
+    return format!("{}?{}", path, query.join("&"));
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    stored_at: u64,
+    expires_at: Option<u64>,
+    body: String,
+}
+
+struct IndexEntry {
+    /// Key of the response stored in the file, as two keys may hash to the same name
+    key: String,
+    size: u64,
+    last_access: u64,
+    expires_at: Option<u64>,
+}
+
+/// A cache of API responses on disk. The index of entries is shared between requests,
+/// but is never locked whilst files are read or written.
+pub struct ResponseCache {
+    config: CacheConfig,
+    index: Mutex<CacheIndex>,
+    /// Used to give each write its own temporary file
+    next_write: AtomicU64,
+}
+
+struct CacheIndex {
+    /// Entries keyed by the name of the file they are stored in
+    entries: HashMap<String, IndexEntry>,
+    report_ends: HashMap<String, u64>,
+    total_size: u64,
+}
+
+impl ResponseCache {
+    /// Opens the cache in the configured directory, creating it if necessary and
+    /// indexing any entries left over from a previous run.
+    pub fn open(config: CacheConfig) -> std::io::Result<ResponseCache> {
+        fs::create_dir_all(&config.directory)?;
+        let mut entries = HashMap::new();
+        let mut total_size = 0;
+        for dir_entry in fs::read_dir(&config.directory)? {
+            let dir_entry = dir_entry?;
+            let f_name = dir_entry.file_name().to_string_lossy().to_string();
+            //Left behind by a write which didn't complete
+            if f_name.ends_with(TEMP_FILE_EXTENSION) {
+                debug!("Removing incomplete cache file {}", f_name);
+                remove_file(&dir_entry.path());
+                continue;
+            }
+            if !f_name.ends_with(CACHE_FILE_EXTENSION) {
+                continue;
+            }
+            let entry = match read_entry(&dir_entry.path()) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Removing unreadable cache file {}: {}", f_name, e);
+                    remove_file(&dir_entry.path());
+                    continue;
+                }
+            };
+            let metadata = dir_entry.metadata()?;
+            let last_access = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(entry.stored_at, |d| d.as_secs());
+            total_size += metadata.len();
+            entries.insert(
+                f_name,
+                IndexEntry {
+                    key: entry.key,
+                    size: metadata.len(),
+                    last_access: last_access,
+                    expires_at: entry.expires_at,
+                },
+            );
+        }
+        info!(
+            "Opened response cache at {:?} containing {} entries ({} bytes)",
+            config.directory,
+            entries.len(),
+            total_size
+        );
+        return Ok(ResponseCache {
+            config: config,
+            index: Mutex::new(CacheIndex {
+                entries: entries,
+                report_ends: HashMap::new(),
+                total_size: total_size,
+            }),
+            next_write: AtomicU64::new(0),
+        });
+    }
+
+    /// Records the time (in milliseconds since the epoch) of the last event logged in
+    /// a report, which is used to decide how long responses for it remain valid.
+    pub fn record_report_end(&self, report_code: &str, end_millis: u64) {
+        self.lock()
+            .report_ends
+            .insert(report_code.to_string(), end_millis);
+    }
+
+    /// Returns the cached response for the given key if one exists and has not expired.
+    /// This reads from disk, so shouldn't be called directly from async code.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let now = now_secs();
+        let f_name = entry_file_name(key);
+        let path = self.config.directory.join(&f_name);
+        let expired = {
+            let mut index = self.lock();
+            let expired = match index.entries.get(&f_name) {
+                Some(entry) if entry.key == key => entry.expires_at.map_or(false, |exp| exp <= now),
+                _ => return None,
+            };
+            if expired {
+                index.remove(&f_name);
+            }
+            expired
+        };
+        if expired {
+            debug!("Cached response for {} has expired", key);
+            remove_file(&path);
+            return None;
+        }
+        let entry = match read_entry(&path) {
+            Ok(entry) if entry.key == key => entry,
+            //Replaced by a response for a key with the same hash since we checked the index
+            Ok(_) => return None,
+            Err(e) => {
+                warn!("Failed to read cached response for {}: {}", key, e);
+                self.lock().remove_if_key(&f_name, key);
+                return None;
+            }
+        };
+        if let Some(idx_entry) = self.lock().entries.get_mut(&f_name) {
+            idx_entry.last_access = now;
+        }
+        debug!("Serving response for {} from cache", key);
+        return Some(entry.body);
+    }
+
+    /// Stores a response for the given key, which belongs to the given report. This
+    /// writes to disk, so shouldn't be called directly from async code.
+    pub fn put(&self, key: &str, report_code: &str, body: &str) {
+        let now = now_secs();
+        let ttl = self.ttl_for_report(report_code, now);
+        let entry = CacheEntry {
+            key: key.to_string(),
+            stored_at: now,
+            expires_at: ttl.map(|ttl| now + ttl),
+            body: body.to_string(),
+        };
+        let f_name = entry_file_name(key);
+        let path = self.config.directory.join(&f_name);
+        let tmp_path = self.config.directory.join(format!(
+            "{}.{}.{}",
+            f_name,
+            self.next_write.fetch_add(1, Ordering::Relaxed),
+            TEMP_FILE_EXTENSION
+        ));
+        let size = match write_entry(&path, &tmp_path, &entry) {
+            Ok(size) => size,
+            Err(e) => {
+                warn!("Failed to write response for {} to cache: {}", key, e);
+                return;
+            }
+        };
+        let evicted = {
+            let mut index = self.lock();
+            //A response for a different key with the same hash has just been overwritten
+            if let Some(old) = index.entries.insert(
+                f_name,
+                IndexEntry {
+                    key: key.to_string(),
+                    size: size,
+                    last_access: now,
+                    expires_at: entry.expires_at,
+                },
+            ) {
+                index.total_size -= old.size;
+            }
+            index.total_size += size;
+            self.evict(&mut index)
+        };
+        for f_name in evicted {
+            remove_file(&self.config.directory.join(f_name));
+        }
+    }
+
+    fn ttl_for_report(&self, report_code: &str, now: u64) -> Option<u64> {
+        let is_live = match self.lock().report_ends.get(report_code) {
+            //If we don't know when the report ended, assume it is still live
+            None => true,
+            Some(end_millis) => {
+                now.saturating_sub(end_millis / 1000) < self.config.live_report_threshold_secs
+            }
+        };
+        if is_live {
+            return Some(self.config.live_report_ttl_secs);
+        }
+        return self.config.finished_report_ttl_secs;
+    }
+
+    /// Removes least recently used entries from the index until the cache is below its
+    /// size cap, returning the names of the files which should be deleted
+    fn evict(&self, index: &mut CacheIndex) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while index.total_size > self.config.max_size_bytes {
+            let lru_file = match index
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+            {
+                Some((f_name, _)) => f_name.clone(),
+                None => break,
+            };
+            if let Some(entry) = index.remove(&lru_file) {
+                debug!("Evicting {} from response cache", entry.key);
+            }
+            evicted.push(lru_file);
+        }
+        return evicted;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheIndex> {
+        return self
+            .index
+            .lock()
+            .expect("Response cache index was poisoned");
+    }
+}
+
+impl CacheIndex {
+    fn remove(&mut self, f_name: &str) -> Option<IndexEntry> {
+        let entry = self.entries.remove(f_name)?;
+        self.total_size -= entry.size;
+        return Some(entry);
+    }
+
+    /// Removes an entry only if it still holds the response for the given key
+    fn remove_if_key(&mut self, f_name: &str, key: &str) {
+        if self
+            .entries
+            .get(f_name)
+            .map_or(false, |entry| entry.key == key)
+        {
+            self.remove(f_name);
+        }
+    }
+}
+
+fn entry_file_name(key: &str) -> String {
+    return format!("{:016x}.{}", fnv1a_hash(key), CACHE_FILE_EXTENSION);
+}
+
+fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("Failed to remove cache file {:?}: {}", path, e);
+    }
+}
+
+#[derive(Debug)]
+enum CacheFileError {
+    IOError(std::io::Error),
+    DecodeError(serde_json::Error),
+}
+
+impl fmt::Display for CacheFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheFileError::IOError(e) => write!(f, "I/O error: {}", e),
+            CacheFileError::DecodeError(e) => write!(f, "invalid cache entry: {}", e),
+        }
+    }
+}
+
+fn read_entry(path: &Path) -> Result<CacheEntry, CacheFileError> {
+    let file = File::open(path).map_err(|e| CacheFileError::IOError(e))?;
+    let mut contents = String::new();
+    GzDecoder::new(file)
+        .read_to_string(&mut contents)
+        .map_err(|e| CacheFileError::IOError(e))?;
+    return serde_json::from_str(&contents).map_err(|e| CacheFileError::DecodeError(e));
+}
+
+/// Writes an entry to disk, returning the size of the compressed file
+fn write_entry(path: &Path, tmp_path: &Path, entry: &CacheEntry) -> Result<u64, CacheFileError> {
+    let contents = serde_json::to_vec(entry).map_err(|e| CacheFileError::DecodeError(e))?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&contents)
+        .map_err(|e| CacheFileError::IOError(e))?;
+    let compressed = encoder.finish().map_err(|e| CacheFileError::IOError(e))?;
+    //Write to a temporary file first so a crash never leaves a truncated entry behind
+    let written = fs::write(tmp_path, &compressed).and_then(|_| fs::rename(tmp_path, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(tmp_path);
+        return Err(CacheFileError::IOError(e));
+    }
+    return Ok(compressed.len() as u64);
+}
+
+/// 64-bit FNV-1a, used for file names as it is stable across builds
+pub(crate) fn fnv1a_hash(key: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in key.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+fn now_secs() -> u64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(name: &str) -> CacheConfig {
+        let dir = std::env::temp_dir().join(format!("kusanagi-cache-test-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        let mut config = CacheConfig::new(&dir.to_string_lossy());
+        config.live_report_threshold_secs = 0;
+        return config;
+    }
+
+    #[test]
+    fn test_cache_key_strips_api_key() {
+        let uri: Uri =
+            "https://www.fflogs.com:443/v1/report/fights/abc?translate=true&api_key=secret"
+                .parse()
+                .unwrap();
+        assert_eq!(cache_key(&uri), "/v1/report/fights/abc?translate=true");
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_eviction() {
+        let mut config = test_config("eviction");
+        config.max_size_bytes = 1;
+        let cache = ResponseCache::open(config.clone()).unwrap();
+        cache.record_report_end("abc", 0);
+        cache.put("/v1/a", "abc", "first");
+        //The cap is smaller than a single entry, so it is evicted immediately
+        assert_eq!(cache.get("/v1/a"), None);
+
+        config.max_size_bytes = 1024 * 1024;
+        let cache = ResponseCache::open(config.clone()).unwrap();
+        cache.record_report_end("abc", 0);
+        cache.put("/v1/b", "abc", "second");
+        assert_eq!(cache.get("/v1/b"), Some("second".to_string()));
+        //Entries survive reopening the cache
+        let cache = ResponseCache::open(config.clone()).unwrap();
+        assert_eq!(cache.get("/v1/b"), Some("second".to_string()));
+        let _ = fs::remove_dir_all(&config.directory);
+    }
+
+    #[test]
+    fn test_open_removes_incomplete_writes() {
+        let config = test_config("incomplete");
+        fs::create_dir_all(&config.directory).unwrap();
+        let stray = config.directory.join("0123456789abcdef.json.gz.0.tmp");
+        fs::write(&stray, b"partial").unwrap();
+        let cache = ResponseCache::open(config.clone()).unwrap();
+        assert!(!stray.exists());
+        assert_eq!(cache.lock().total_size, 0);
+        let _ = fs::remove_dir_all(&config.directory);
+    }
+}
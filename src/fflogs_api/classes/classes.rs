@@ -1 +1,107 @@
+//! API calls and types for fetching the list of playable classes and their specs. As
+//! with zones, these are fetched once and then kept for the lifetime of the process.
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
 
+use std::sync::Arc;
+
+use http::uri::Uri;
+use lazy_static::*;
+use tokio::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use log::info;
+
+lazy_static! {
+    static ref CLASSES: Mutex<Option<Arc<Vec<Class>>>> = Mutex::new(None);
+}
+
+/// Returns the list of classes, only making a request to the API the first time it is
+/// called
+pub async fn get_classes(api_client: &FFLogsApiClient) -> Result<Arc<Vec<Class>>, ApiError> {
+    let mut classes = CLASSES.lock().await;
+    if let Some(cached) = classes.as_ref() {
+        return Ok(cached.clone());
+    }
+    let fetched = Arc::new(request_classes(api_client).await?);
+    *classes = Some(fetched.clone());
+    return Ok(fetched);
+}
+
+pub async fn request_classes(api_client: &FFLogsApiClient) -> Result<Vec<Class>, ApiError> {
+    info!("Making API request to classes endpoint.");
+    let url = construct_url(api_client)?;
+    let resp = api_client.run_request(url).await?;
+    let res: Vec<Class> =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+pub fn construct_url(api_client: &FFLogsApiClient) -> Result<Uri, ApiError> {
+    let query = QueryParams {
+        api_key: api_client.api_key().to_owned(),
+    };
+    return api_client.request_uri("/v1/classes", query);
+}
+
+/// Finds the spec referred to by the `icon` or `type` string of an actor in a report.
+/// These use the spec name with spaces removed (e.g. `BlackMage` for `Black Mage`), so
+/// names are compared ignoring spaces and case.
+pub fn find_spec<'a>(classes: &'a [Class], icon_or_type: &str) -> Option<(&'a Class, &'a Spec)> {
+    let wanted = normalise_name(icon_or_type);
+    return classes.iter().find_map(|class| {
+        class
+            .specs
+            .iter()
+            .find(|spec| normalise_name(&spec.name) == wanted)
+            .map(|spec| (class, spec))
+    });
+}
+
+fn normalise_name(name: &str) -> String {
+    return name
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    api_key: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Class {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "specs", default)]
+    pub specs: Vec<Spec>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Spec {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "name")]
+    pub name: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_spec() {
+        let msg = r#"[{"id":1,"name":"Global","specs":[
+            {"id":1,"name":"Astrologian"},{"id":3,"name":"Black Mage"},{"id":19,"name":"White Mage"}]}]"#;
+        let classes: Vec<Class> = serde_json::from_str(msg).unwrap();
+        let (class, spec) = find_spec(&classes, "BlackMage").unwrap();
+        assert_eq!(class.id, 1);
+        assert_eq!(spec.id, 3);
+        assert_eq!(find_spec(&classes, "whitemage").unwrap().1.id, 19);
+        assert!(find_spec(&classes, "Boss").is_none());
+    }
+}
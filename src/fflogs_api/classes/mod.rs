@@ -1,2 +1,2 @@
-//TODO
+//! API calls and types for the classes endpoint
 pub mod classes;
@@ -0,0 +1,198 @@
+//! Transports which record API traffic to a fixture directory and replay it later,
+//! allowing analyses of real reports to be reproduced offline. Each exchange is stored
+//! as a JSON file named after a hash of the request uri (with the `api_key` parameter
+//! removed, so fixtures can be shared without leaking keys).
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use futures::future::BoxFuture;
+use hyper::body::Buf;
+use hyper::{Body, Method, Request, Response};
+use serde::{Deserialize, Serialize};
+
+use super::api::ApiError;
+use super::cache::{cache_key, fnv1a_hash};
+use super::transport::HttpTransport;
+
+use log::{debug, warn};
+
+/// Whether API traffic should be recorded to or replayed from a fixture directory
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixtureMode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub struct FixtureConfig {
+    pub mode: FixtureMode,
+    pub directory: PathBuf,
+}
+
+/// A single recorded request and the response the API gave to it
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct RecordedExchange {
+    #[serde(rename = "request")]
+    pub request: String,
+    #[serde(rename = "status")]
+    pub status: u16,
+    #[serde(rename = "body")]
+    pub body: String,
+}
+
+/// Returns the key identifying a request, along with the request itself rebuilt so that
+/// it can still be sent. Requests other than GETs (such as GraphQL queries) are told
+/// apart by their body as well as their uri.
+async fn exchange_key(request: Request<Body>) -> Result<(String, Request<Body>), ApiError> {
+    let key = cache_key(request.uri());
+    if request.method() == Method::GET {
+        return Ok((key, request));
+    }
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::aggregate(body)
+        .await
+        .map_err(|err| ApiError::RequestError(err))?
+        .to_bytes();
+    let key = format!(
+        "{} {} {}",
+        parts.method,
+        key,
+        String::from_utf8_lossy(&body)
+    );
+    return Ok((key, Request::from_parts(parts, Body::from(body))));
+}
+
+fn fixture_path(directory: &Path, key: &str) -> PathBuf {
+    return directory.join(format!("{:016x}.json", fnv1a_hash(key)));
+}
+
+/// Transport which passes requests through to another transport, saving every
+/// response it receives to the fixture directory
+pub struct RecordingTransport {
+    inner: Box<dyn HttpTransport>,
+    directory: PathBuf,
+}
+
+impl RecordingTransport {
+    pub fn new(inner: Box<dyn HttpTransport>, directory: &Path) -> std::io::Result<Self> {
+        fs::create_dir_all(directory)?;
+        return Ok(RecordingTransport {
+            inner: inner,
+            directory: directory.to_path_buf(),
+        });
+    }
+}
+
+impl HttpTransport for RecordingTransport {
+    fn send(&self, request: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+        return Box::pin(async move {
+            let (key, request) = exchange_key(request).await?;
+            let res = self.inner.send(request).await?;
+            let (parts, body) = res.into_parts();
+            let body = hyper::body::aggregate(body)
+                .await
+                .map_err(|err| ApiError::RequestError(err))?
+                .to_bytes();
+            let exchange = RecordedExchange {
+                request: key.clone(),
+                status: parts.status.as_u16(),
+                body: String::from_utf8_lossy(&body).to_string(),
+            };
+            let path = fixture_path(&self.directory, &key);
+            let write_res = serde_json::to_vec_pretty(&exchange)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| fs::write(&path, contents).map_err(|e| e.to_string()));
+            match write_res {
+                Ok(()) => debug!("Recorded response for {} to {:?}", key, path),
+                Err(e) => warn!("Failed to record response for {}: {}", key, e),
+            }
+            return Ok(Response::from_parts(parts, Body::from(body)));
+        });
+    }
+}
+
+/// Transport which never touches the network, instead serving responses previously
+/// saved by a RecordingTransport
+pub struct ReplayTransport {
+    directory: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new(directory: &Path) -> Self {
+        return ReplayTransport {
+            directory: directory.to_path_buf(),
+        };
+    }
+
+    fn load(&self, key: &str) -> Result<Response<Body>, ApiError> {
+        let path = fixture_path(&self.directory, key);
+        let contents =
+            fs::read_to_string(&path).map_err(|_| ApiError::FixtureNotFound(key.to_string()))?;
+        let exchange: RecordedExchange =
+            serde_json::from_str(&contents).map_err(|err| ApiError::ResponseFormatError(err))?;
+        if exchange.request != key {
+            return Err(ApiError::FixtureNotFound(key.to_string()));
+        }
+        debug!("Replaying recorded response for {}", key);
+        return Response::builder()
+            .status(exchange.status)
+            .body(Body::from(exchange.body))
+            .map_err(|err| ApiError::RequestConstructionError(err));
+    }
+}
+
+impl HttpTransport for ReplayTransport {
+    fn send(&self, request: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+        return Box::pin(async move {
+            let (key, _) = exchange_key(request).await?;
+            return self.load(&key);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::{new_fflogs_api_client_with_transport, ApiClientConfig};
+    use tokio::runtime::Runtime;
+
+    struct StaticTransport;
+
+    impl HttpTransport for StaticTransport {
+        fn send(&self, _: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+            let res = Response::new(Body::from("recorded body"));
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let dir = std::env::temp_dir().join("kusanagi-fixture-test");
+        let _ = fs::remove_dir_all(&dir);
+        let query = [("translate", "true"), ("api_key", "secret")];
+
+        let recorder = RecordingTransport::new(Box::new(StaticTransport), &dir).unwrap();
+        let client =
+            new_fflogs_api_client_with_transport("secret", Default::default(), Box::new(recorder));
+        let uri = client.request_uri("/v1/report/fights/abc", &query).unwrap();
+        let recorded = Runtime::new().unwrap().block_on(client.run_request(uri));
+        assert_eq!(recorded.unwrap(), "recorded body");
+
+        let replay = ReplayTransport::new(&dir);
+        let mut config: ApiClientConfig = Default::default();
+        config.retry.max_attempts = 1;
+        let client = new_fflogs_api_client_with_transport("other", config, Box::new(replay));
+        let uri = client.request_uri("/v1/report/fights/abc", &query).unwrap();
+        let replayed = Runtime::new().unwrap().block_on(client.run_request(uri));
+        assert_eq!(replayed.unwrap(), "recorded body");
+
+        let uri = client.request_uri("/v1/report/fights/xyz", &query).unwrap();
+        match Runtime::new().unwrap().block_on(client.run_request(uri)) {
+            Err(ApiError::FixtureNotFound(key)) => {
+                assert_eq!(key, "/v1/report/fights/xyz?translate=true")
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,8 +1,13 @@
 pub mod api;
+pub mod cache;
 pub mod classes;
+pub mod fixtures;
 pub mod parses;
 pub mod rankings;
+pub mod rate_limit;
 pub mod report;
 pub mod reports;
+pub mod transport;
 pub mod types;
+pub mod v2;
 pub mod zones;
@@ -1 +1,151 @@
+//! API calls and types for fetching every parse a single character has logged
+use crate::fflogs_api::api::{encode_path_segment, ApiError, FFLogsApiClient};
+use crate::fflogs_api::types::{RankingMetric, RankingTimeframe};
 
+use std::collections::HashMap;
+
+use http::uri::Uri;
+
+use serde::{Deserialize, Serialize};
+
+use log::info;
+
+pub async fn request_character_parses(
+    character_name: &str,
+    server: &str,
+    region: &str,
+    filters: CharacterParsesFilters,
+    client: &FFLogsApiClient,
+) -> Result<Vec<CharacterParse>, ApiError> {
+    info!(
+        "Making API request to parses.character endpoint for {} on {} ({}).",
+        character_name, server, region
+    );
+    let url = construct_url(character_name, server, region, filters, client)?;
+    let resp = client.run_request(url).await?;
+    let res: Vec<CharacterParse> =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+/// Attempts to construct the URL from which a request can be made to the character
+/// parses endpoint.
+pub fn construct_url(
+    character_name: &str,
+    server: &str,
+    region: &str,
+    filters: CharacterParsesFilters,
+    client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
+    let path = format!(
+        "/v1/parses/character/{}/{}/{}",
+        encode_path_segment(character_name),
+        encode_path_segment(server),
+        encode_path_segment(region)
+    );
+    let query = QueryParams {
+        filters: filters,
+        api_key: client.api_key().to_owned(),
+    };
+    return client.request_uri(&path, query);
+}
+
+/// Picks out the parse with the highest percentile for each encounter, ordered by
+/// encounter ID
+pub fn best_parses_by_encounter(parses: &[CharacterParse]) -> Vec<&CharacterParse> {
+    let mut best: HashMap<i64, &CharacterParse> = HashMap::new();
+    for parse in parses {
+        let entry = best.entry(parse.encounter_id).or_insert(parse);
+        if parse.percentile > entry.percentile {
+            *entry = parse;
+        }
+    }
+    let mut res: Vec<&CharacterParse> = best.into_iter().map(|(_, parse)| parse).collect();
+    res.sort_by_key(|parse| parse.encounter_id);
+    return res;
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    #[serde(flatten)]
+    filters: CharacterParsesFilters,
+    api_key: String,
+}
+
+/// Filters which may be applied to a character's parses during the API request
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct CharacterParsesFilters {
+    pub zone: Option<i64>,
+    pub encounter: Option<i64>,
+    pub metric: Option<RankingMetric>,
+    pub bracket: Option<i64>,
+    pub partition: Option<i64>,
+    pub timeframe: Option<RankingTimeframe>,
+    pub compare: Option<i64>,
+    #[serde(rename = "includeCombatantInfo")]
+    pub include_combatant_info: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CharacterParse {
+    #[serde(rename = "encounterID")]
+    pub encounter_id: i64,
+    #[serde(rename = "encounterName")]
+    pub encounter_name: String,
+    #[serde(rename = "class")]
+    pub class: String,
+    #[serde(rename = "spec")]
+    pub spec: String,
+    #[serde(rename = "rank")]
+    pub rank: i64,
+    #[serde(rename = "outOf")]
+    pub out_of: i64,
+    #[serde(rename = "duration")]
+    pub duration: u64,
+    #[serde(rename = "startTime")]
+    pub start_time: u64,
+    #[serde(rename = "reportID")]
+    pub report_code: String,
+    #[serde(rename = "fightID")]
+    pub fight_id: i64,
+    #[serde(rename = "difficulty")]
+    pub difficulty: i64,
+    #[serde(rename = "size")]
+    pub size: Option<i64>,
+    #[serde(rename = "characterID")]
+    pub character_id: i64,
+    #[serde(rename = "characterName")]
+    pub character_name: String,
+    #[serde(rename = "server")]
+    pub server: String,
+    #[serde(rename = "percentile")]
+    pub percentile: f64,
+    #[serde(rename = "ilvlKeyOrPatch")]
+    pub ilvl_key_or_patch: f64,
+    #[serde(rename = "total")]
+    pub total: f64,
+    #[serde(rename = "estimated")]
+    pub estimated: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::new_fflogs_api_client;
+
+    #[test]
+    fn test_parse_and_pick_best() {
+        let msg = r#"[
+            {"encounterID":1050,"encounterName":"The Epic of Alexander","class":"Global","spec":"WhiteMage","rank":120,"outOf":3000,"duration":900000,"startTime":1590000000000,"reportID":"aaaa","fightID":3,"difficulty":100,"size":8,"characterID":1,"characterName":"Kiiroi Yuki","server":"Cerberus","percentile":61.2,"ilvlKeyOrPatch":5.3,"total":7012.4,"estimated":false},
+            {"encounterID":1050,"encounterName":"The Epic of Alexander","class":"Global","spec":"WhiteMage","rank":40,"outOf":3000,"duration":880000,"startTime":1590600000000,"reportID":"bbbb","fightID":9,"difficulty":100,"size":8,"characterID":1,"characterName":"Kiiroi Yuki","server":"Cerberus","percentile":88.9,"ilvlKeyOrPatch":5.3,"total":7540.0}]"#;
+        let parses: Vec<CharacterParse> = serde_json::from_str(msg).unwrap();
+        let best = best_parses_by_encounter(&parses);
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].report_code, "bbbb");
+
+        let client = new_fflogs_api_client("key");
+        let url =
+            construct_url("Kiiroi Yuki", "Cerberus", "EU", Default::default(), &client).unwrap();
+        assert_eq!(url.path(), "/v1/parses/character/Kiiroi%20Yuki/Cerberus/EU");
+    }
+}
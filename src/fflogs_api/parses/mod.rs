@@ -1,2 +1,2 @@
-//TODO
+//! API calls and types for the character parses endpoint
 pub mod character;
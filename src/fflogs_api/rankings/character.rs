@@ -1 +1,171 @@
+//! API calls and types for fetching a single character's best rankings on each
+//! encounter
+use crate::fflogs_api::api::{encode_path_segment, ApiError, FFLogsApiClient};
+use crate::fflogs_api::types::{RankingMetric, RankingTimeframe};
 
+use http::uri::Uri;
+
+use serde::{Deserialize, Serialize};
+
+use log::info;
+
+pub async fn request_character_rankings(
+    character_name: &str,
+    server: &str,
+    region: &str,
+    filters: CharacterRankingsFilters,
+    client: &FFLogsApiClient,
+) -> Result<Vec<CharacterRanking>, ApiError> {
+    info!(
+        "Making API request to rankings.character endpoint for {} on {} ({}).",
+        character_name, server, region
+    );
+    let url = construct_url(character_name, server, region, filters, client)?;
+    let resp = client.run_request(url).await?;
+    let res: Vec<CharacterRanking> =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+/// Attempts to construct the URL from which a request can be made to the character
+/// rankings endpoint.
+pub fn construct_url(
+    character_name: &str,
+    server: &str,
+    region: &str,
+    filters: CharacterRankingsFilters,
+    client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
+    let path = format!(
+        "/v1/rankings/character/{}/{}/{}",
+        encode_path_segment(character_name),
+        encode_path_segment(server),
+        encode_path_segment(region)
+    );
+    let query = QueryParams {
+        filters: filters,
+        api_key: client.api_key().to_owned(),
+    };
+    return client.request_uri(&path, query);
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    #[serde(flatten)]
+    filters: CharacterRankingsFilters,
+    api_key: String,
+}
+
+/// Filters which may be applied to a character's rankings during the API request
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct CharacterRankingsFilters {
+    pub zone: Option<i64>,
+    pub encounter: Option<i64>,
+    pub metric: Option<RankingMetric>,
+    pub bracket: Option<i64>,
+    pub partition: Option<i64>,
+    pub timeframe: Option<RankingTimeframe>,
+    pub compare: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CharacterRanking {
+    #[serde(rename = "encounterID")]
+    pub encounter_id: i64,
+    #[serde(rename = "encounterName")]
+    pub encounter_name: String,
+    #[serde(rename = "class")]
+    pub class: String,
+    #[serde(rename = "spec")]
+    pub spec: String,
+    #[serde(rename = "rank")]
+    pub rank: i64,
+    #[serde(rename = "outOf")]
+    pub out_of: i64,
+    #[serde(rename = "duration")]
+    pub duration: u64,
+    #[serde(rename = "startTime")]
+    pub start_time: u64,
+    #[serde(rename = "reportID")]
+    pub report_code: String,
+    #[serde(rename = "fightID")]
+    pub fight_id: i64,
+    #[serde(rename = "difficulty")]
+    pub difficulty: i64,
+    #[serde(rename = "characterID")]
+    pub character_id: i64,
+    #[serde(rename = "characterName")]
+    pub character_name: String,
+    #[serde(rename = "server")]
+    pub server: String,
+    #[serde(rename = "percentile")]
+    pub percentile: f64,
+    #[serde(rename = "ilvlKeyOrPatch")]
+    pub ilvl_key_or_patch: f64,
+    #[serde(rename = "total")]
+    pub total: f64,
+    #[serde(rename = "estimated")]
+    pub estimated: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::{new_fflogs_api_client, new_fflogs_api_client_with_transport};
+    use crate::fflogs_api::transport::HttpTransport;
+    use futures::future::BoxFuture;
+    use hyper::{Body, Request, Response};
+    use tokio::runtime::Runtime;
+
+    const RANKINGS: &'static str = r#"[
+        {"encounterID":1050,"encounterName":"The Epic of Alexander","class":"Global","spec":"Dancer","rank":310,"outOf":4200,"duration":910000,"startTime":1590000000000,"reportID":"cccc","fightID":12,"difficulty":100,"size":8,"characterID":7,"characterName":"Y'shtola Rhul","server":"Omega","percentile":92.6,"ilvlKeyOrPatch":5.3,"total":9101.7,"estimated":true}]"#;
+
+    struct StaticTransport;
+
+    impl HttpTransport for StaticTransport {
+        fn send(&self, _: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+            let res = Response::new(Body::from(RANKINGS));
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_construct_url_encodes_path() {
+        let client = new_fflogs_api_client("key");
+        let filters = CharacterRankingsFilters {
+            encounter: Some(1050),
+            metric: Some(RankingMetric::Dps),
+            ..Default::default()
+        };
+        let url = construct_url("Y'shtola Rhul", "Omega", "EU", filters, &client).unwrap();
+        assert_eq!(
+            url.path(),
+            "/v1/rankings/character/Y%27shtola%20Rhul/Omega/EU"
+        );
+        assert_eq!(url.query(), Some("encounter=1050&metric=dps&api_key=key"));
+    }
+
+    #[test]
+    fn test_request_character_rankings() {
+        let client = new_fflogs_api_client_with_transport(
+            "key",
+            Default::default(),
+            Box::new(StaticTransport),
+        );
+        let rankings = Runtime::new()
+            .unwrap()
+            .block_on(request_character_rankings(
+                "Y'shtola Rhul",
+                "Omega",
+                "EU",
+                Default::default(),
+                &client,
+            ))
+            .unwrap();
+        assert_eq!(rankings.len(), 1);
+        assert_eq!(rankings[0].character_name, "Y'shtola Rhul");
+        assert_eq!(rankings[0].report_code, "cccc");
+        assert_eq!(rankings[0].percentile, 92.6);
+        assert_eq!(rankings[0].estimated, Some(true));
+    }
+}
@@ -1 +1,253 @@
+//! API calls and types for fetching the rankings of every logged kill of an encounter
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
+use crate::fflogs_api::types::RankingMetric;
 
+use std::collections::VecDeque;
+
+use futures::future::BoxFuture;
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use http::uri::Uri;
+use std::pin::*;
+
+use serde::{Deserialize, Serialize};
+
+use log::info;
+
+// //////////////////////////////// //
+// ////// Request Structs  //////// //
+// //////////////////////////////// //
+
+/// Requests a single page of rankings for the given encounter
+pub async fn request_encounter_rankings(
+    encounter_id: i64,
+    filters: EncounterRankingsFilters,
+    client: &FFLogsApiClient,
+) -> Result<EncounterRankingsPage, ApiError> {
+    info!(
+        "Making API request to rankings.encounter endpoint for encounter {}.",
+        encounter_id
+    );
+    let url = construct_url(encounter_id, filters, client)?;
+    let resp = client.run_request(url).await?;
+    let res: EncounterRankingsPage =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+/// Returns a stream of rankings for the given encounter, starting from the page set in
+/// the filters (or the first page) and fetching further pages as they are needed
+pub fn get_encounter_rankings_stream<'a>(
+    encounter_id: i64,
+    filters: EncounterRankingsFilters,
+    client: &'a FFLogsApiClient,
+) -> EncounterRankingsStream<'a> {
+    let first_page = filters.page.unwrap_or(1);
+    return EncounterRankingsStream {
+        encounter_id: encounter_id,
+        filters: filters,
+        client: client,
+        rankings: VecDeque::new(),
+        next_page_number: Some(first_page),
+        next_page: None,
+    };
+}
+
+pub struct EncounterRankingsStream<'a> {
+    encounter_id: i64,
+    filters: EncounterRankingsFilters,
+    client: &'a FFLogsApiClient,
+    rankings: VecDeque<EncounterRanking>,
+    next_page_number: Option<i64>,
+    next_page: Option<BoxFuture<'a, Result<EncounterRankingsPage, ApiError>>>,
+}
+
+impl<'a> Stream for EncounterRankingsStream<'a> {
+    type Item = Result<EncounterRanking, ApiError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<EncounterRanking, ApiError>>> {
+        loop {
+            if let Some(ranking) = self.rankings.pop_front() {
+                return Poll::Ready(Some(Ok(ranking)));
+            }
+            let page_number = match self.next_page_number {
+                None => return Poll::Ready(None),
+                Some(page_number) => page_number,
+            };
+            if self.next_page.is_none() {
+                let mut filters = self.filters.clone();
+                filters.page = Some(page_number);
+                let fut = Box::pin(request_encounter_rankings(
+                    self.encounter_id,
+                    filters,
+                    self.client,
+                ));
+                self.next_page = Some(fut);
+            }
+            let page = match self.next_page.as_mut().unwrap().as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(res) => {
+                    self.next_page = None;
+                    res
+                }
+            };
+            match page {
+                //Stop rather than retrying the same page forever
+                Err(e) => {
+                    self.next_page_number = None;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Ok(page) => {
+                    self.next_page_number = if page.has_more_pages && !page.rankings.is_empty() {
+                        Some(page_number + 1)
+                    } else {
+                        None
+                    };
+                    self.rankings.extend(page.rankings);
+                }
+            }
+        }
+    }
+}
+
+/// Attempts to construct the URL from which a request can be made to the encounter
+/// rankings endpoint.
+pub fn construct_url(
+    encounter_id: i64,
+    filters: EncounterRankingsFilters,
+    client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
+    let path = format!("/v1/rankings/encounter/{}", encounter_id);
+    let query = QueryParams {
+        filters: filters,
+        api_key: client.api_key().to_owned(),
+    };
+    return client.request_uri(&path, query);
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    #[serde(flatten)]
+    filters: EncounterRankingsFilters,
+    api_key: String,
+}
+
+/// Filters which may be applied to the list of rankings during the API request
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct EncounterRankingsFilters {
+    pub metric: Option<RankingMetric>,
+    pub size: Option<i64>,
+    pub difficulty: Option<i64>,
+    pub partition: Option<i64>,
+    pub class: Option<i64>,
+    pub spec: Option<i64>,
+    pub bracket: Option<i64>,
+    pub server: Option<String>,
+    pub region: Option<String>,
+    pub page: Option<i64>,
+    pub filter: Option<String>,
+}
+
+// //////////////////////////////// //
+// ////// Response Structs //////// //
+// //////////////////////////////// //
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct EncounterRankingsPage {
+    #[serde(rename = "page")]
+    pub page: i64,
+    #[serde(rename = "hasMorePages")]
+    pub has_more_pages: bool,
+    #[serde(rename = "count")]
+    pub count: Option<i64>,
+    #[serde(rename = "rankings")]
+    pub rankings: Vec<EncounterRanking>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct EncounterRanking {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "class")]
+    pub class: Option<i64>,
+    #[serde(rename = "spec")]
+    pub spec: Option<i64>,
+    #[serde(rename = "total")]
+    pub total: f64,
+    #[serde(rename = "duration")]
+    pub duration: u64,
+    #[serde(rename = "startTime")]
+    pub start_time: u64,
+    #[serde(rename = "fightID")]
+    pub fight_id: i64,
+    #[serde(rename = "reportID")]
+    pub report_code: String,
+    #[serde(rename = "guildName")]
+    pub guild_name: Option<String>,
+    #[serde(rename = "serverName")]
+    pub server_name: Option<String>,
+    #[serde(rename = "regionName")]
+    pub region_name: Option<String>,
+    #[serde(rename = "hidden")]
+    pub hidden: Option<bool>,
+    #[serde(rename = "patch")]
+    pub patch: Option<f64>,
+    #[serde(rename = "itemLevel")]
+    pub item_level: Option<f64>,
+    #[serde(rename = "size")]
+    pub size: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::new_fflogs_api_client_with_transport;
+    use crate::fflogs_api::transport::HttpTransport;
+    use futures::stream::StreamExt;
+    use hyper::{Body, Request, Response};
+    use tokio::runtime::Runtime;
+
+    struct PagedTransport;
+
+    impl HttpTransport for PagedTransport {
+        fn send(&self, request: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+            let query = request.uri().query().unwrap_or("").to_string();
+            let body = if query.contains("page=1") {
+                r#"{"page":1,"hasMorePages":true,"count":3,"rankings":[
+                    {"name":"Static A","total":900.5,"duration":900500,"startTime":1590000000000,"fightID":3,"reportID":"aaaa"},
+                    {"name":"Static B","total":910.0,"duration":910000,"startTime":1590000000000,"fightID":7,"reportID":"bbbb"}]}"#
+            } else {
+                r#"{"page":2,"hasMorePages":false,"count":3,"rankings":[
+                    {"name":"Static C","total":920.0,"duration":920000,"startTime":1590000000000,"fightID":1,"reportID":"cccc","serverName":"Cerberus"}]}"#
+            };
+            return Box::pin(futures::future::ready(Ok(Response::new(Body::from(body)))));
+        }
+    }
+
+    #[test]
+    fn test_rankings_stream_follows_pages() {
+        let client = new_fflogs_api_client_with_transport(
+            "key",
+            Default::default(),
+            Box::new(PagedTransport),
+        );
+        let filters = EncounterRankingsFilters {
+            metric: Some(RankingMetric::Speed),
+            ..Default::default()
+        };
+        let url = construct_url(1050, filters.clone(), &client).unwrap();
+        assert_eq!(url.path(), "/v1/rankings/encounter/1050");
+        assert!(url.query().unwrap().contains("metric=speed"));
+
+        let stream = get_encounter_rankings_stream(1050, filters, &client);
+        let rankings: Vec<_> = Runtime::new().unwrap().block_on(stream.collect());
+        let codes: Vec<String> = rankings
+            .into_iter()
+            .map(|r| r.unwrap().report_code)
+            .collect();
+        assert_eq!(codes, vec!["aaaa", "bbbb", "cccc"]);
+    }
+}
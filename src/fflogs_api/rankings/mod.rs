@@ -1,3 +1,3 @@
-//TODO
+//! API calls and types for the encounter and character rankings endpoints
 pub mod character;
 pub mod encounter;
@@ -0,0 +1,125 @@
+//! A client-side token bucket used to keep the number of requests we send to the
+//! FFLogs API within our key's quota, no matter which endpoint they are for.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::delay_for;
+
+use log::debug;
+
+/// Configuration for the rate limiter shared by all requests made by an FFLogsApiClient
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Sustained number of requests which may be sent each minute
+    pub requests_per_minute: u32,
+    /// Number of requests which may be sent back-to-back before throttling kicks in
+    pub burst: u32,
+    /// Maximum number of requests which may be in flight at once
+    pub max_concurrent_requests: usize,
+}
+
+impl std::default::Default for RateLimitConfig {
+    fn default() -> Self {
+        return RateLimitConfig {
+            requests_per_minute: 240,
+            burst: 10,
+            max_concurrent_requests: 4,
+        };
+    }
+}
+
+pub struct RateLimiter {
+    bucket: Mutex<TokenBucket>,
+    concurrency: Semaphore,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Attempts to take a token, returning how long to wait before trying again if
+    /// the bucket is empty.
+    fn try_take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        let wait_secs = (1.0 - self.tokens) / self.refill_per_sec;
+        return Err(Duration::from_secs_f64(wait_secs));
+    }
+}
+
+/// Proof that a request has been allowed through the rate limiter. The concurrency
+/// slot is released when this is dropped.
+pub struct RateLimitPermit<'a> {
+    _permit: SemaphorePermit<'a>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> RateLimiter {
+        let capacity = config.burst.max(1) as f64;
+        return RateLimiter {
+            bucket: Mutex::new(TokenBucket {
+                tokens: capacity,
+                capacity: capacity,
+                refill_per_sec: config.requests_per_minute.max(1) as f64 / 60.0,
+                last_refill: Instant::now(),
+            }),
+            concurrency: Semaphore::new(config.max_concurrent_requests.max(1)),
+        };
+    }
+
+    /// Waits until a request may be sent, returning a permit which should be held
+    /// until the request completes.
+    pub async fn acquire(&self) -> RateLimitPermit<'_> {
+        let permit = self.concurrency.acquire().await;
+        loop {
+            let res = self
+                .bucket
+                .lock()
+                .expect("Rate limiter state was poisoned")
+                .try_take();
+            match res {
+                Ok(()) => break,
+                Err(wait) => {
+                    debug!(
+                        "Rate limit reached, waiting {}ms before sending request",
+                        wait.as_millis()
+                    );
+                    delay_for(wait).await;
+                }
+            }
+        }
+        return RateLimitPermit { _permit: permit };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_empties_after_burst() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            requests_per_minute: 60,
+            burst: 3,
+            max_concurrent_requests: 1,
+        });
+        let mut bucket = limiter.bucket.lock().unwrap();
+        assert!(bucket.try_take().is_ok());
+        assert!(bucket.try_take().is_ok());
+        assert!(bucket.try_take().is_ok());
+        let wait = bucket.try_take().unwrap_err();
+        assert!(wait <= Duration::from_secs(1));
+        assert!(wait > Duration::from_millis(900));
+    }
+}
@@ -1,16 +1,21 @@
 //! API calls and types which allow you to fetch a list of events that occurred during
 //! an FFLogs report
-use crate::fflogs_api::api::{fflogs_request, ApiError, FFLogsApiClient};
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
+use crate::fflogs_api::report::filter::FilterExpression;
+use crate::fflogs_api::report::source::ReportDataSource;
 use crate::fflogs_api::types::{Ability, Resources, Source, Target};
 
 use futures::future::BoxFuture;
 use futures::stream::Stream;
 use futures::task::{Context, Poll};
 use http::uri::Uri;
+use std::collections::VecDeque;
 use std::pin::*;
+use std::sync::Arc;
 
-use serde::de::{SeqAccess, Visitor};
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use log::{debug, info, warn};
@@ -50,32 +55,114 @@ pub fn get_event_iterator<'a>(
     view: EventsView,
     report_code: &'a str,
     filters: EventFilters,
-    client: &'a FFLogsApiClient,
+    client: &'a dyn ReportDataSource,
 ) -> EventsStream<'a> {
-    let init_events_list = ReportEventsList {
-        events: Vec::new(),
-        next_page_timestamp: Some(filters.start),
-    };
-    let res = EventsStream {
-        view: view.clone(),
-        report_code: report_code,
-        filters: filters.clone(),
-        client: client,
-        events: init_events_list,
-        current_event_position: 0,
-        next_page: None,
-    };
-    return res;
+    let fetch_page: PageFetcher<'a> =
+        Box::new(move |page_filters| client.fetch_events(view, report_code, page_filters));
+    return EventsStream::new(filters, fetch_page);
 }
 
-pub struct EventsStream<'a> {
+/// Returns a stream of events which owns everything it needs, so that it can be moved
+/// into a spawned task or stored for as long as is needed. The client may be any data
+/// source, such as an `Arc<FFLogsApiClient>`.
+pub fn get_owned_event_stream(
     view: EventsView,
-    report_code: &'a str,
+    report_code: String,
     filters: EventFilters,
-    client: &'a FFLogsApiClient,
-    events: ReportEventsList,
-    current_event_position: usize,
+    client: Arc<dyn ReportDataSource>,
+) -> OwnedEventsStream {
+    let report_code = Arc::new(report_code);
+    let fetch_page: PageFetcher<'static> = Box::new(move |page_filters| {
+        let client = client.clone();
+        let report_code = report_code.clone();
+        return Box::pin(async move {
+            return client.fetch_events(view, &report_code, page_filters).await;
+        });
+    });
+    return EventsStream::new(filters, fetch_page);
+}
+
+/// Something which can fetch the page of events starting at the start time in the given
+/// filters
+type PageFetcher<'a> = Box<
+    dyn Fn(EventFilters) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> + Send + Sync + 'a,
+>;
+
+pub struct EventsStream<'a> {
+    filters: EventFilters,
+    fetch_page: PageFetcher<'a>,
+    pages: VecDeque<VecDeque<ReportEvent>>,
+    next_page_start: Option<u64>,
     next_page: Option<BoxFuture<'a, Result<ReportEventsList, ApiError>>>,
+    pending_error: Option<ApiError>,
+    read_ahead: usize,
+}
+
+/// An events stream which doesn't borrow anything, and so can be sent between tasks
+pub type OwnedEventsStream = EventsStream<'static>;
+
+/// The number of pages an events stream fetches ahead of the one being read by default
+pub const DEFAULT_READ_AHEAD_PAGES: usize = 1;
+
+impl<'a> EventsStream<'a> {
+    fn new(filters: EventFilters, fetch_page: PageFetcher<'a>) -> Self {
+        let first_page_start = filters.start;
+        return EventsStream {
+            filters: filters,
+            fetch_page: fetch_page,
+            pages: VecDeque::new(),
+            next_page_start: Some(first_page_start),
+            next_page: None,
+            pending_error: None,
+            read_ahead: DEFAULT_READ_AHEAD_PAGES,
+        };
+    }
+
+    /// Sets how many pages beyond the one currently being read should be fetched in
+    /// advance. With a read-ahead of zero, the next page is only requested once every
+    /// event already fetched has been consumed.
+    pub fn with_read_ahead(mut self, pages: usize) -> Self {
+        self.read_ahead = pages;
+        return self;
+    }
+
+    /// Starts fetching the next page if there is one and we don't already have enough
+    /// buffered, then drives any fetch in progress.
+    fn poll_fetch(&mut self, cx: &mut Context<'_>) {
+        loop {
+            if self.next_page.is_none() && self.pending_error.is_none() {
+                match self.next_page_start {
+                    Some(start) if start >= self.filters.end => self.next_page_start = None,
+                    Some(start) if self.pages.len() <= self.read_ahead => {
+                        self.filters.start = start;
+                        self.next_page = Some((self.fetch_page)(self.filters.clone()));
+                    }
+                    _ => (),
+                }
+            }
+            let next_page_fut = match self.next_page.as_mut() {
+                None => return,
+                Some(fut) => fut,
+            };
+            match next_page_fut.as_mut().poll(cx) {
+                Poll::Pending => return,
+                Poll::Ready(Err(e)) => {
+                    self.next_page = None;
+                    self.pending_error = Some(e);
+                    return;
+                }
+                Poll::Ready(Ok(page)) => {
+                    self.next_page = None;
+                    if page.events.is_empty() {
+                        self.next_page_start = None;
+                    } else {
+                        self.next_page_start = page.next_page_timestamp;
+                        self.pages.push_back(page.events.into());
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl<'a> Stream for EventsStream<'a> {
@@ -85,57 +172,27 @@ impl<'a> Stream for EventsStream<'a> {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<ReportEvent, ApiError>>> {
-        let remaining_events = self.events.events.len() - (self.current_event_position);
-        //If there are no locally stored events left
-        if remaining_events <= 0 {
-            match self.events.next_page_timestamp {
-                //If we have no locally stored events left and also no way to fetch more
-                None => return Poll::Ready(None),
-                //If we have run out of locally stored events but still have filters ready to get the next page
-                Some(new_start) => {
-                    if new_start >= self.filters.end {
-                        return Poll::Ready(None);
-                    } else {
-                        self.filters.start = new_start;
-                    }
-                    let next_page_fut = match self.next_page.as_mut() {
-                        None => {
-                            let fut = request_events(
-                                self.view,
-                                self.report_code,
-                                self.filters.clone(),
-                                self.client,
-                            );
-                            let res = Box::pin(fut);
-                            self.next_page = Some(res);
-                            self.next_page.as_mut().unwrap()
-                        }
-                        Some(fut) => fut,
-                    };
-                    let next_page_completion = next_page_fut.as_mut().poll(cx);
-                    match next_page_completion {
-                        Poll::Pending => return Poll::Pending,
-                        Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
-                        Poll::Ready(Ok(mut next_page)) => {
-                            self.next_page = None;
-                            if next_page.events.len() < 1 {
-                                return Poll::Ready(None);
-                            }
-                            self.events.events.append(&mut next_page.events);
-                            self.events.next_page_timestamp = next_page.next_page_timestamp;
-                            let res = self.events.events[self.current_event_position].clone();
-                            self.current_event_position += 1;
-                            return Poll::Ready(Some(Ok(res)));
-                        }
-                    }
+        self.poll_fetch(cx);
+        //Hand out buffered events first, dropping each page once it has been consumed
+        while let Some(page) = self.pages.front_mut() {
+            if let Some(ev) = page.pop_front() {
+                if page.is_empty() {
+                    self.pages.pop_front();
                 }
+                return Poll::Ready(Some(Ok(ev)));
             }
-        //If we have more locally stored events, return them
-        } else {
-            let res = self.events.events[self.current_event_position].clone();
-            self.current_event_position += 1;
-            return Poll::Ready(Some(Ok(res)));
+            self.pages.pop_front();
+        }
+        //Errors are only reported once the events before them have been read, after
+        //which the stream ends rather than retrying the same page forever
+        if let Some(e) = self.pending_error.take() {
+            self.next_page_start = None;
+            return Poll::Ready(Some(Err(e)));
+        }
+        if self.next_page.is_some() {
+            return Poll::Pending;
         }
+        return Poll::Ready(None);
     }
 }
 
@@ -149,20 +206,19 @@ pub async fn request_events(
         "Making API request to request.events endpoint on report code {}.",
         report_code
     );
-    let url = construct_url(&view, report_code, filters, client.api_key())?;
+    let url = construct_url(&view, report_code, filters, client)?;
     let target_url = url.to_string();
-    let resp: String = client.run_request(url).await?;
+    let resp: String = client.run_report_request(url, report_code).await?;
     let res: ReportEventsList = serde_json::from_str(&resp).map_err(|err| {
         warn!("Failed to decode response due to error {:?}.", err);
         debug!("Response contents: {:?}.", resp);
         return ApiError::ResponseFormatError(err);
     })?;
-    if res
-        .events
-        .iter()
-        .any(|e| e == &ReportEvent::UnparseableEvent)
-    {
-        warn!(
+    if res.events.iter().any(|e| match e {
+        ReportEvent::Unknown { .. } => true,
+        _ => false,
+    }) {
+        debug!(
             "Unknown event type recieved when parsing API call to {}",
             target_url
         );
@@ -176,14 +232,14 @@ pub fn construct_url(
     view: &EventsView,
     report_code: &str,
     filters: EventFilters,
-    api_key: &str,
+    client: &FFLogsApiClient,
 ) -> Result<Uri, ApiError> {
     let path = construct_path(&view, report_code);
     let query = QueryParams {
         filters: filters,
-        api_key: api_key.to_owned(),
+        api_key: client.api_key().to_owned(),
     };
-    return fflogs_request(&path, query);
+    return client.request_uri(&path, query);
 }
 
 fn construct_path(view: &EventsView, report_code: &str) -> String {
@@ -192,7 +248,7 @@ fn construct_path(view: &EventsView, report_code: &str) -> String {
 
 /// Enum representing the different views that can be requested from the API.
 /// This affects the types of event which will be returned.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum EventsView {
     Summary,
     DamageDone,
@@ -255,7 +311,7 @@ pub struct EventFilters {
     pub encounter: Option<i64>,
     pub wipes: Option<i64>,
     pub difficulty: Option<i64>,
-    pub filter: Option<String>,
+    pub filter: Option<FilterExpression>,
     pub translate: Option<bool>,
 }
 
@@ -276,56 +332,45 @@ impl std::default::Default for Hostility {
 // //////////////////////////////// //
 // ////// Response Structs //////// //
 // //////////////////////////////// //
-//TODO: Fix deserializing into default variant when fixed
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct ReportEventsList {
-    #[serde(rename = "events")] //, deserialize_with = "events_list_deserialize")]
+    #[serde(rename = "events", deserialize_with = "events_list_deserialize")]
     pub events: Vec<ReportEvent>,
     #[serde(rename = "nextPageTimestamp")]
     pub next_page_timestamp: Option<u64>,
 }
 
-fn _events_list_deserialize<'de, D>(deserializer: D) -> Result<Vec<ReportEvent>, D::Error>
+/// Decodes each event in a list separately, so that a single malformed event is kept
+/// as an `Unknown` event rather than causing the whole page to fail.
+fn events_list_deserialize<'de, D>(deserializer: D) -> Result<Vec<ReportEvent>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    struct EventsVisitor {}
-
-    impl<'de> Visitor<'de> for EventsVisitor {
-        type Value = Vec<ReportEvent>;
-
-        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-            return formatter.write_str("an array of report events");
-        }
+    let raw_events: Vec<Value> = Vec::deserialize(deserializer)?;
+    return Ok(decode_events(raw_events));
+}
 
-        fn visit_seq<S>(self, mut seq: S) -> Result<Self::Value, S::Error>
-        where
-            S: SeqAccess<'de>,
-        {
-            let mut res = Vec::with_capacity(seq.size_hint().unwrap_or(0));
-            loop {
-                match seq.next_element() {
-                    Ok(Some(ev)) => res.push(ev),
-                    Ok(None) => break,
-                    Err(err) => {
-                        //res.push(ReportEvent::UnparseableEvent(err.to_string()));
-                        warn!(
-                            "Found unknown event type whilst parsing events list: {}",
-                            err
-                        );
-                    }
-                };
-            }
-            return Ok(res);
-        }
-    }
-    let visitor = EventsVisitor {};
-    return deserializer.deserialize_any(visitor);
+/// Decodes a list of raw events, keeping any which fail to decode as `Unknown` events
+pub(crate) fn decode_events(raw_events: Vec<Value>) -> Vec<ReportEvent> {
+    return raw_events
+        .into_iter()
+        .map(
+            |raw| match <ReportEvent as Deserialize>::deserialize(&raw) {
+                Ok(ev) => ev,
+                Err(err) => {
+                    warn!("Failed to decode event, keeping it as raw JSON: {}", err);
+                    debug!("Event contents: {}", raw);
+                    ReportEvent::from_raw(raw)
+                }
+            },
+        )
+        .collect();
 }
 
-///A single event fired during an FFLogs report
+///A single event fired during an FFLogs report. Events of a type which isn't modelled
+///here are kept as `Unknown`, along with their raw JSON.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-#[serde(tag = "type")]
+#[serde(remote = "Self", tag = "type")]
 pub enum ReportEvent {
     #[serde(rename = "calculateddamage")]
     CalculatedDamage(CalculatedDamage),
@@ -363,17 +408,125 @@ pub enum ReportEvent {
     Death(Death),
     #[serde(rename = "limitbreakupdate")]
     LimitBreakUpdate(LimitBreakUpdate),
-    #[serde(other)]
-    UnparseableEvent,
+    #[serde(rename = "absorbed")]
+    Absorbed(Absorbed),
+    #[serde(rename = "summon")]
+    Summon(Summon),
+    #[serde(rename = "interrupt")]
+    Interrupt(Interrupt),
+    #[serde(rename = "dispel")]
+    Dispel(Dispel),
+    #[serde(rename = "resourcechange")]
+    ResourceChange(ResourceChange),
+    #[serde(rename = "encounterstart")]
+    EncounterStart(EncounterStart),
+    #[serde(rename = "encounterend")]
+    EncounterEnd(EncounterEnd),
+    #[serde(rename = "targetabilityupdate")]
+    TargetabilityUpdate(TargetabilityUpdate),
+    #[serde(rename = "headmarker")]
+    HeadMarker(HeadMarker),
+    #[serde(rename = "tether")]
+    Tether(Tether),
+    #[serde(rename = "combatantinfo")]
+    CombatantInfo(CombatantInfo),
+    #[serde(rename = "instakill")]
+    Instakill(Instakill),
+    #[serde(skip)]
+    Unknown {
+        event_type: String,
+        timestamp: Option<u64>,
+        raw: Value,
+    },
 }
 
+/// The `type` tags of every event modelled by `ReportEvent`, other than `Unknown`
+const KNOWN_EVENT_TYPES: &[&str] = &[
+    "calculateddamage",
+    "damage",
+    "calculatedheal",
+    "heal",
+    "begincast",
+    "cast",
+    "applybuff",
+    "refreshbuff",
+    "applybuffstack",
+    "removebuff",
+    "removebuffstack",
+    "applydebuff",
+    "refreshdebuff",
+    "applydebuffstack",
+    "removedebuff",
+    "removedebuffstack",
+    "death",
+    "limitbreakupdate",
+    "absorbed",
+    "summon",
+    "interrupt",
+    "dispel",
+    "resourcechange",
+    "encounterstart",
+    "encounterend",
+    "targetabilityupdate",
+    "headmarker",
+    "tether",
+    "combatantinfo",
+    "instakill",
+];
+
 impl Default for ReportEvent {
     fn default() -> ReportEvent {
-        return ReportEvent::UnparseableEvent;
+        return ReportEvent::from_raw(Value::Null);
+    }
+}
+
+impl Serialize for ReportEvent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            ReportEvent::Unknown { raw, .. } => raw.serialize(serializer),
+            _ => ReportEvent::serialize(self, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ReportEvent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = Value::deserialize(deserializer)?;
+        //Events of a type we don't know about are kept, but malformed events of a known
+        //type (or without a type at all) are still an error
+        let is_known = match raw.get("type").and_then(|t| t.as_str()) {
+            Some(ev_type) => KNOWN_EVENT_TYPES.contains(&ev_type),
+            None => true,
+        };
+        if !is_known {
+            return Ok(ReportEvent::from_raw(raw));
+        }
+        return ReportEvent::deserialize(&raw).map_err(|err| D::Error::custom(err));
     }
 }
 
 impl ReportEvent {
+    /// Wraps a raw event as an `Unknown` event, picking out its type and timestamp
+    pub fn from_raw(raw: Value) -> ReportEvent {
+        let event_type = raw
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("")
+            .to_string();
+        let timestamp = raw.get("timestamp").and_then(|t| t.as_u64());
+        return ReportEvent::Unknown {
+            event_type: event_type,
+            timestamp: timestamp,
+            raw: raw,
+        };
+    }
+
     pub fn get_timestamp(&self) -> Option<u64> {
         match self {
             ReportEvent::CalculatedDamage(ev) => Some(ev.timestamp),
@@ -394,7 +547,19 @@ impl ReportEvent {
             ReportEvent::RemoveDebuffStack(ev) => Some(ev.timestamp),
             ReportEvent::Death(ev) => Some(ev.timestamp),
             ReportEvent::LimitBreakUpdate(ev) => Some(ev.timestamp),
-            ReportEvent::UnparseableEvent => None,
+            ReportEvent::Absorbed(ev) => Some(ev.timestamp),
+            ReportEvent::Summon(ev) => Some(ev.timestamp),
+            ReportEvent::Interrupt(ev) => Some(ev.timestamp),
+            ReportEvent::Dispel(ev) => Some(ev.timestamp),
+            ReportEvent::ResourceChange(ev) => Some(ev.timestamp),
+            ReportEvent::EncounterStart(ev) => Some(ev.timestamp),
+            ReportEvent::EncounterEnd(ev) => Some(ev.timestamp),
+            ReportEvent::TargetabilityUpdate(ev) => Some(ev.timestamp),
+            ReportEvent::HeadMarker(ev) => Some(ev.timestamp),
+            ReportEvent::Tether(ev) => Some(ev.timestamp),
+            ReportEvent::CombatantInfo(ev) => Some(ev.timestamp),
+            ReportEvent::Instakill(ev) => Some(ev.timestamp),
+            ReportEvent::Unknown { timestamp, .. } => *timestamp,
         }
     }
 }
@@ -732,6 +897,232 @@ pub struct LimitBreakUpdate {
     pub bars: i32,
 }
 
+///Event fired when damage is absorbed by a shield
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Absorbed {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Ability,
+    #[serde(rename = "attackerID")]
+    pub attacker_id: Option<i64>,
+    #[serde(rename = "attackerIsFriendly")]
+    pub attacker_is_friendly: Option<bool>,
+    #[serde(rename = "amount")]
+    pub amount: Option<i64>,
+    #[serde(rename = "extraAbility")]
+    pub extra_ability: Option<Ability>,
+}
+
+///Event fired when an actor summons a pet or other actor
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Summon {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Ability,
+}
+
+///Event fired when a cast is interrupted, with the interrupted cast as the extra ability
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Interrupt {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Ability,
+    #[serde(rename = "extraAbility")]
+    pub extra_ability: Option<Ability>,
+}
+
+///Event fired when a buff or debuff is dispelled, with the removed aura as the extra
+///ability
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Dispel {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Ability,
+    #[serde(rename = "extraAbility")]
+    pub extra_ability: Option<Ability>,
+    #[serde(rename = "isBuff")]
+    pub is_buff: Option<bool>,
+}
+
+///Event fired when an actor gains or spends a resource such as MP
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ResourceChange {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Option<Ability>,
+    #[serde(rename = "resourceChange")]
+    pub resource_change: i64,
+    #[serde(rename = "resourceChangeType")]
+    pub resource_change_type: i64,
+    #[serde(rename = "otherResourceChange")]
+    pub other_resource_change: Option<i64>,
+    #[serde(rename = "maxResourceAmount")]
+    pub max_resource_amount: Option<i64>,
+    #[serde(rename = "waste")]
+    pub waste: Option<i64>,
+}
+
+///Event fired when an encounter begins
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct EncounterStart {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(rename = "encounterID")]
+    pub encounter_id: i64,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+    #[serde(rename = "difficulty")]
+    pub difficulty: Option<i64>,
+    #[serde(rename = "size")]
+    pub size: Option<i64>,
+}
+
+///Event fired when an encounter ends, whether in a kill or a wipe
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct EncounterEnd {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(rename = "encounterID")]
+    pub encounter_id: i64,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+    #[serde(rename = "difficulty")]
+    pub difficulty: Option<i64>,
+    #[serde(rename = "size")]
+    pub size: Option<i64>,
+    #[serde(rename = "kill")]
+    pub kill: Option<bool>,
+}
+
+///Event fired when an actor becomes targetable or untargetable, e.g. when a boss jumps
+///away between phases
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TargetabilityUpdate {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Option<Ability>,
+    #[serde(rename = "targetable")]
+    pub targetable: i64,
+}
+
+impl TargetabilityUpdate {
+    pub fn is_targetable(&self) -> bool {
+        return self.targetable != 0;
+    }
+}
+
+///Event fired when a marker appears over an actor's head, usually signalling a mechanic
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct HeadMarker {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "markerID")]
+    pub marker_id: i64,
+}
+
+///Event fired when a tether is created between two actors
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Tether {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "tetherID")]
+    pub tether_id: Option<i64>,
+}
+
+///Event fired at the start of a fight with the gear and stats of each player
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CombatantInfo {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(rename = "sourceID")]
+    pub source_id: i64,
+    #[serde(rename = "level")]
+    pub level: Option<i64>,
+    #[serde(rename = "gear", default)]
+    pub gear: Vec<CombatantGear>,
+    #[serde(rename = "auras", default)]
+    pub auras: Vec<CombatantAura>,
+}
+
+///A single piece of gear worn by a player
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CombatantGear {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "slot")]
+    pub slot: Option<i64>,
+    #[serde(rename = "itemLevel")]
+    pub item_level: Option<i64>,
+    #[serde(rename = "icon")]
+    pub icon: Option<String>,
+}
+
+///An aura active on a player at the start of a fight
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CombatantAura {
+    #[serde(rename = "source")]
+    pub source: i64,
+    #[serde(rename = "ability")]
+    pub ability: i64,
+    #[serde(rename = "stacks")]
+    pub stacks: Option<i64>,
+    #[serde(rename = "icon")]
+    pub icon: Option<String>,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+}
+
+///Event fired when an actor is killed outright, e.g. by a failed mechanic or enrage
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Instakill {
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub target: Option<Target>,
+    #[serde(rename = "ability")]
+    pub ability: Option<Ability>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -792,4 +1183,208 @@ mod tests {
         let tgt: ReportEvent = serde_json::from_str(msg).unwrap();
         assert_eq!(tgt, res);
     }
+
+    #[test]
+    fn test_unknown_and_malformed_events_are_kept() {
+        let msg = r#"{"events":[
+            {"timestamp":100,"type":"limitbreakupdate","value":5000,"bars":1},
+            {"timestamp":200,"type":"somethingnew","sourceID":3},
+            {"timestamp":300,"type":"limitbreakupdate","value":"lots"}],
+            "nextPageTimestamp":400}"#;
+        let page: ReportEventsList = serde_json::from_str(msg).unwrap();
+        assert_eq!(page.events.len(), 3);
+        let timestamps: Vec<Option<u64>> = page.events.iter().map(|e| e.get_timestamp()).collect();
+        assert_eq!(timestamps, vec![Some(100), Some(200), Some(300)]);
+        match &page.events[1] {
+            ReportEvent::Unknown {
+                event_type, raw, ..
+            } => {
+                assert_eq!(event_type, "somethingnew");
+                assert_eq!(raw["sourceID"], 3);
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+        //Unknown events serialize back to their original JSON
+        let reserialized = serde_json::to_value(&page.events[2]).unwrap();
+        assert_eq!(reserialized["value"], "lots");
+        //A lone malformed event of a known type is still an error
+        let single: Result<ReportEvent, _> =
+            serde_json::from_str(r#"{"timestamp":300,"type":"limitbreakupdate"}"#);
+        assert!(single.is_err());
+        let untyped: Result<ReportEvent, _> = serde_json::from_str(r#"{"timestamp":300}"#);
+        assert!(untyped.is_err());
+    }
+
+    #[test]
+    fn test_new_event_types_round_trip() {
+        let messages = vec![
+            r#"{"timestamp":10,"type":"absorbed","sourceID":1,"sourceIsFriendly":true,"targetID":2,"targetIsFriendly":true,"ability":{"name":"Galvanize","guid":1000297,"type":1,"abilityIcon":"012000-012801.png"},"attackerID":5,"attackerIsFriendly":false,"amount":4000,"extraAbility":{"name":"attack","guid":7,"type":128,"abilityIcon":"000000-000101.png"}}"#,
+            r#"{"timestamp":11,"type":"summon","sourceID":1,"sourceIsFriendly":true,"targetID":3,"targetIsFriendly":true,"ability":{"name":"Summon Eos","guid":17215,"type":1024,"abilityIcon":"002000-002791.png"}}"#,
+            r#"{"timestamp":12,"type":"interrupt","sourceID":1,"sourceIsFriendly":true,"targetID":5,"targetIsFriendly":false,"ability":{"name":"Interject","guid":7538,"type":1,"abilityIcon":"000000-000808.png"},"extraAbility":{"name":"Hard Cast","guid":1234,"type":1024,"abilityIcon":null}}"#,
+            r#"{"timestamp":13,"type":"dispel","sourceID":1,"sourceIsFriendly":true,"targetID":2,"targetIsFriendly":true,"ability":{"name":"Esuna","guid":7568,"type":1024,"abilityIcon":"000000-000884.png"},"extraAbility":{"name":"Doom","guid":1000910,"type":1,"abilityIcon":null},"isBuff":false}"#,
+            r#"{"timestamp":14,"type":"resourcechange","sourceID":1,"sourceIsFriendly":true,"targetID":1,"targetIsFriendly":true,"ability":{"name":"Lucid Dreaming","guid":7562,"type":1,"abilityIcon":null},"resourceChange":550,"resourceChangeType":0,"otherResourceChange":0,"maxResourceAmount":10000,"waste":0}"#,
+            r#"{"timestamp":15,"type":"encounterstart","encounterID":1050,"name":"The Epic of Alexander","difficulty":100,"size":8}"#,
+            r#"{"timestamp":16,"type":"encounterend","encounterID":1050,"name":"The Epic of Alexander","difficulty":100,"size":8,"kill":false}"#,
+            r#"{"timestamp":17,"type":"targetabilityupdate","sourceID":5,"sourceIsFriendly":false,"targetID":5,"targetIsFriendly":false,"targetable":0}"#,
+            r#"{"timestamp":18,"type":"headmarker","sourceID":5,"sourceIsFriendly":false,"targetID":2,"targetIsFriendly":true,"markerID":23}"#,
+            r#"{"timestamp":19,"type":"tether","sourceID":5,"sourceIsFriendly":false,"targetID":2,"targetIsFriendly":true,"tetherID":17}"#,
+            r#"{"timestamp":20,"type":"combatantinfo","sourceID":1,"level":80,"gear":[{"id":28000,"slot":0,"itemLevel":480,"icon":"033000-033123.png"}],"auras":[{"source":1,"ability":1000048,"stacks":1,"icon":"016000-016202.png","name":"Well Fed"}]}"#,
+            r#"{"timestamp":21,"type":"instakill","sourceID":5,"sourceIsFriendly":false,"targetID":2,"targetIsFriendly":true,"ability":{"name":"Enrage","guid":9999,"type":1024,"abilityIcon":null}}"#,
+        ];
+        for (idx, msg) in messages.into_iter().enumerate() {
+            let ev: ReportEvent = serde_json::from_str(msg).unwrap();
+            if let ReportEvent::Unknown { event_type, .. } = &ev {
+                panic!("Event type {} was not recognised", event_type);
+            }
+            assert_eq!(ev.get_timestamp(), Some(10 + idx as u64));
+            let reserialized = serde_json::to_string(&ev).unwrap();
+            let round_tripped: ReportEvent = serde_json::from_str(&reserialized).unwrap();
+            assert_eq!(round_tripped, ev);
+        }
+    }
+
+    #[test]
+    fn test_targetability_and_headmarker_fields() {
+        let msg = r#"{"timestamp":17,"type":"targetabilityupdate","sourceID":5,"sourceIsFriendly":false,"targetable":1}"#;
+        match serde_json::from_str(msg).unwrap() {
+            ReportEvent::TargetabilityUpdate(ev) => {
+                assert!(ev.is_targetable());
+                assert_eq!(ev.source.get_id(), Some(5));
+                assert_eq!(ev.target, None);
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+        let msg = r#"{"timestamp":18,"type":"headmarker","sourceID":5,"sourceIsFriendly":false,"targetID":2,"targetIsFriendly":true,"markerID":23}"#;
+        match serde_json::from_str(msg).unwrap() {
+            ReportEvent::HeadMarker(ev) => {
+                assert_eq!(ev.marker_id, 23);
+                assert_eq!(ev.target.and_then(|t| t.get_id()), Some(2));
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+    }
+
+    #[derive(Default)]
+    struct StaticSource {
+        requests: std::sync::atomic::AtomicUsize,
+        /// Pages starting at or after this time fail to load
+        fail_from: Option<u64>,
+    }
+
+    impl ReportDataSource for StaticSource {
+        fn fetch_fights<'a>(
+            &'a self,
+            _: &'a str,
+            _: bool,
+        ) -> BoxFuture<'a, Result<crate::fflogs_api::report::fights::ReportFightsList, ApiError>>
+        {
+            return Box::pin(futures::future::ready(Err(ApiError::NotImplementedError)));
+        }
+
+        fn fetch_events<'a>(
+            &'a self,
+            _: EventsView,
+            _: &'a str,
+            filters: EventFilters,
+        ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> {
+            //Pages of two events each, ten milliseconds apart
+            self.requests
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let start = filters.start;
+            if self.fail_from.map_or(false, |fail_from| start >= fail_from) {
+                return Box::pin(futures::future::ready(Err(ApiError::NotImplementedError)));
+            }
+            let events = (0..2)
+                .map(|i| ReportEvent::from_raw(serde_json::json!({"timestamp": start + i * 10})))
+                .collect();
+            let res = ReportEventsList {
+                events: events,
+                next_page_timestamp: Some(start + 20),
+            };
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_owned_stream_can_be_spawned() {
+        use futures::stream::StreamExt;
+
+        let filters = EventFilters {
+            start: 0,
+            end: 60,
+            ..Default::default()
+        };
+        let stream = get_owned_event_stream(
+            EventsView::Casts,
+            "abcd".to_string(),
+            filters,
+            Arc::new(StaticSource::default()),
+        );
+        let timestamps = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(async move {
+                let handle = tokio::spawn(async move {
+                    return stream
+                        .map(|ev| ev.unwrap().get_timestamp().unwrap())
+                        .collect::<Vec<u64>>()
+                        .await;
+                });
+                return handle.await.unwrap();
+            });
+        assert_eq!(timestamps, vec![0, 10, 20, 30, 40, 50]);
+    }
+
+    #[test]
+    fn test_stream_reads_ahead() {
+        use futures::stream::StreamExt;
+        use std::sync::atomic::Ordering;
+
+        let filters = EventFilters {
+            start: 0,
+            end: 60,
+            ..Default::default()
+        };
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let source = StaticSource::default();
+        let mut stream = get_event_iterator(EventsView::Casts, "abcd", filters.clone(), &source)
+            .with_read_ahead(0);
+        rt.block_on(stream.next());
+        assert_eq!(source.requests.load(Ordering::SeqCst), 1);
+
+        let source = StaticSource::default();
+        let mut stream = get_event_iterator(EventsView::Casts, "abcd", filters, &source);
+        rt.block_on(stream.next());
+        assert_eq!(source.requests.load(Ordering::SeqCst), 2);
+        let rest: Vec<u64> = rt.block_on(
+            stream
+                .map(|ev| ev.unwrap().get_timestamp().unwrap())
+                .collect(),
+        );
+        assert_eq!(rest, vec![10, 20, 30, 40, 50]);
+        assert_eq!(source.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_stream_ends_after_error() {
+        use futures::stream::StreamExt;
+        use std::sync::atomic::Ordering;
+
+        let filters = EventFilters {
+            start: 0,
+            end: 60,
+            ..Default::default()
+        };
+        let source = StaticSource {
+            fail_from: Some(20),
+            ..Default::default()
+        };
+        let stream = get_event_iterator(EventsView::Casts, "abcd", filters, &source);
+        let results: Vec<Option<u64>> = tokio::runtime::Runtime::new().unwrap().block_on(
+            stream
+                .map(|ev| ev.ok().and_then(|ev| ev.get_timestamp()))
+                .collect(),
+        );
+        assert_eq!(results, vec![Some(0), Some(10), None]);
+        assert_eq!(source.requests.load(Ordering::SeqCst), 2);
+    }
 }
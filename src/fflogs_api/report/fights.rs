@@ -1,6 +1,6 @@
 //! API calls and types for fetching a list of fights contained within a report as well
 //! as related metadata
-use crate::fflogs_api::api::{fflogs_request, ApiError, FFLogsApiClient};
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
 use crate::fflogs_api::types::{Instance, Unit};
 
 use http::uri::Uri;
@@ -18,20 +18,33 @@ pub async fn request_fights(
         "Making API request to report.fights endpoint on report with code {}.",
         report_code
     );
-    let url = construct_url(report_code, translate, api_client.api_key())?;
-    let resp = api_client.run_request(url).await?;
+    let url = construct_url(report_code, translate, api_client)?;
+    let resp = api_client
+        .run_report_request_with_end(url, report_code, |body| {
+            serde_json::from_str::<ReportEnd>(body)
+                .ok()
+                .and_then(|r| r.end)
+        })
+        .await?;
     let res: ReportFightsList =
         serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    if let Some(end) = res.end {
+        api_client.record_report_end(report_code, end);
+    }
     return Ok(res);
 }
 
-pub fn construct_url(report_code: &str, translate: bool, api_key: &str) -> Result<Uri, ApiError> {
+pub fn construct_url(
+    report_code: &str,
+    translate: bool,
+    api_client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
     let path = construct_path(report_code);
     let query = QueryParams {
         translate: Some(translate),
-        api_key: api_key.to_owned(),
+        api_key: api_client.api_key().to_owned(),
     };
-    return fflogs_request(&path, query);
+    return api_client.request_uri(&path, query);
 }
 
 fn construct_path(report_code: &str) -> String {
@@ -44,6 +57,13 @@ struct QueryParams {
     api_key: String,
 }
 
+/// Just the end time of a report, read from a fights response before it is cached
+#[derive(Deserialize)]
+struct ReportEnd {
+    #[serde(rename = "end")]
+    end: Option<u64>,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct ReportFightsList {
     #[serde(rename = "fights")]
@@ -77,7 +97,7 @@ pub struct ReportFightsList {
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Fight {
     #[serde(rename = "id")]
-    id: i64,
+    pub id: i64,
     #[serde(rename = "start_time")]
     pub start_time: u64,
     #[serde(rename = "end_time")]
@@ -107,3 +127,55 @@ pub struct Fight {
     #[serde(rename = "lastPhaseForPercentageDisplay")]
     pub last_phase_for_percentage_display: Option<i64>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::{new_fflogs_api_client_with_transport, ApiClientConfig};
+    use crate::fflogs_api::cache::CacheConfig;
+    use crate::fflogs_api::transport::HttpTransport;
+    use futures::future::BoxFuture;
+    use hyper::{Body, Request, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+
+    const FINISHED_REPORT: &'static str = r#"{"fights": [], "friendlies": [], "enemies": [],
+        "friendlyPets": [], "enemyPets": [], "phases": [], "start": 1000, "end": 2000}"#;
+
+    struct CountingTransport {
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl HttpTransport for CountingTransport {
+        fn send(&self, _: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let res = Response::new(Body::from(FINISHED_REPORT));
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_finished_report_fights_are_cached_long_term() {
+        let dir = std::env::temp_dir().join("kusanagi-fights-cache-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut cache_config = CacheConfig::new(&dir.to_string_lossy());
+        //Anything treated as live expires immediately
+        cache_config.live_report_ttl_secs = 0;
+        cache_config.finished_report_ttl_secs = Some(60 * 60);
+        let mut config: ApiClientConfig = Default::default();
+        config.cache = Some(cache_config);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let transport = CountingTransport {
+            requests: requests.clone(),
+        };
+        let client = new_fflogs_api_client_with_transport("key", config, Box::new(transport));
+
+        let mut rt = Runtime::new().unwrap();
+        let first = rt.block_on(request_fights("abc", true, &client)).unwrap();
+        let second = rt.block_on(request_fights("abc", true, &client)).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,316 @@
+//! A typed representation of the FFLogs filter expression language, which can be
+//! passed to the events and tables endpoints to select exactly the events wanted.
+//!
+//! Expressions are built up from fields, e.g.
+//! `field(FilterField::AbilityId).is_in(vec![1, 2]).and(event_type("begincast"))`
+//! serializes to `ability.id IN (1, 2) AND type = "begincast"`.
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// Expressions which always or never match, as the API rejects empty lists
+const ALWAYS_TRUE: &'static str = "1 = 1";
+const ALWAYS_FALSE: &'static str = "1 = 0";
+
+/// A property of an event which can be filtered on
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterField {
+    Type,
+    AbilityId,
+    AbilityName,
+    SourceId,
+    SourceName,
+    TargetId,
+    TargetName,
+    EncounterPhase,
+    Timestamp,
+    /// Any other field, written exactly as given
+    Other(String),
+}
+
+impl fmt::Display for FilterField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            FilterField::Type => write!(f, "type"),
+            FilterField::AbilityId => write!(f, "ability.id"),
+            FilterField::AbilityName => write!(f, "ability.name"),
+            FilterField::SourceId => write!(f, "source.id"),
+            FilterField::SourceName => write!(f, "source.name"),
+            FilterField::TargetId => write!(f, "target.id"),
+            FilterField::TargetName => write!(f, "target.name"),
+            FilterField::EncounterPhase => write!(f, "encounterPhase"),
+            FilterField::Timestamp => write!(f, "timestamp"),
+            FilterField::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A literal value which a field can be compared against
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Int(i64),
+    Str(String),
+}
+
+impl fmt::Display for FilterValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            FilterValue::Int(val) => write!(f, "{}", val),
+            FilterValue::Str(val) => {
+                write!(f, "\"{}\"", val.replace('\\', "\\\\").replace('"', "\\\""))
+            }
+        }
+    }
+}
+
+impl From<i64> for FilterValue {
+    fn from(val: i64) -> Self {
+        return FilterValue::Int(val);
+    }
+}
+
+impl From<&str> for FilterValue {
+    fn from(val: &str) -> Self {
+        return FilterValue::Str(val.to_string());
+    }
+}
+
+impl From<String> for FilterValue {
+    fn from(val: String) -> Self {
+        return FilterValue::Str(val);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            CompareOp::Eq => write!(f, "="),
+            CompareOp::NotEq => write!(f, "!="),
+            CompareOp::Lt => write!(f, "<"),
+            CompareOp::LtEq => write!(f, "<="),
+            CompareOp::Gt => write!(f, ">"),
+            CompareOp::GtEq => write!(f, ">="),
+        }
+    }
+}
+
+/// A filter expression, which serializes to the string form expected by the API
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpression {
+    Compare(FilterField, CompareOp, FilterValue),
+    In(FilterField, Vec<FilterValue>),
+    And(Vec<FilterExpression>),
+    Or(Vec<FilterExpression>),
+    Not(Box<FilterExpression>),
+    /// Matches every event between an event matching `from` and one matching `to`
+    InRange {
+        from: Box<FilterExpression>,
+        to: Box<FilterExpression>,
+    },
+    /// A hand-written expression, used exactly as given
+    Raw(String),
+}
+
+impl FilterExpression {
+    pub fn and(self, other: FilterExpression) -> FilterExpression {
+        return match self {
+            FilterExpression::And(mut exprs) => {
+                exprs.push(other);
+                FilterExpression::And(exprs)
+            }
+            expr => FilterExpression::And(vec![expr, other]),
+        };
+    }
+
+    pub fn or(self, other: FilterExpression) -> FilterExpression {
+        return match self {
+            FilterExpression::Or(mut exprs) => {
+                exprs.push(other);
+                FilterExpression::Or(exprs)
+            }
+            expr => FilterExpression::Or(vec![expr, other]),
+        };
+    }
+
+    pub fn in_range(from: FilterExpression, to: FilterExpression) -> FilterExpression {
+        return FilterExpression::InRange {
+            from: Box::new(from),
+            to: Box::new(to),
+        };
+    }
+
+    /// Writes the expression, wrapped in brackets if it could otherwise be split up by
+    /// the operator around it
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterExpression::And(_)
+            | FilterExpression::Or(_)
+            | FilterExpression::InRange { .. }
+            | FilterExpression::Raw(_) => write!(f, "({})", self),
+            _ => write!(f, "{}", self),
+        }
+    }
+}
+
+impl fmt::Display for FilterExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            FilterExpression::Compare(field, op, val) => write!(f, "{} {} {}", field, op, val),
+            FilterExpression::In(_, vals) if vals.is_empty() => write!(f, "{}", ALWAYS_FALSE),
+            FilterExpression::In(field, vals) => {
+                let vals: Vec<String> = vals.iter().map(|v| v.to_string()).collect();
+                write!(f, "{} IN ({})", field, vals.join(", "))
+            }
+            FilterExpression::And(exprs) if exprs.is_empty() => write!(f, "{}", ALWAYS_TRUE),
+            FilterExpression::Or(exprs) if exprs.is_empty() => write!(f, "{}", ALWAYS_FALSE),
+            FilterExpression::And(exprs) | FilterExpression::Or(exprs) => {
+                let joiner = match &self {
+                    FilterExpression::And(_) => " AND ",
+                    _ => " OR ",
+                };
+                for (idx, expr) in exprs.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, "{}", joiner)?;
+                    }
+                    expr.fmt_operand(f)?;
+                }
+                Ok(())
+            }
+            FilterExpression::Not(expr) => {
+                write!(f, "NOT ")?;
+                expr.fmt_operand(f)
+            }
+            FilterExpression::InRange { from, to } => {
+                write!(f, "IN RANGE FROM {} TO {} END", from, to)
+            }
+            FilterExpression::Raw(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+impl std::ops::Not for FilterExpression {
+    type Output = FilterExpression;
+
+    fn not(self) -> FilterExpression {
+        return FilterExpression::Not(Box::new(self));
+    }
+}
+
+impl Serialize for FilterExpression {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        return serializer.serialize_str(&self.to_string());
+    }
+}
+
+/// Refers to a field so that it can be compared against values
+pub struct FieldRef(FilterField);
+
+pub fn field(field: FilterField) -> FieldRef {
+    return FieldRef(field);
+}
+
+impl FieldRef {
+    pub fn eq<V: Into<FilterValue>>(self, val: V) -> FilterExpression {
+        return FilterExpression::Compare(self.0, CompareOp::Eq, val.into());
+    }
+
+    pub fn not_eq<V: Into<FilterValue>>(self, val: V) -> FilterExpression {
+        return FilterExpression::Compare(self.0, CompareOp::NotEq, val.into());
+    }
+
+    pub fn lt<V: Into<FilterValue>>(self, val: V) -> FilterExpression {
+        return FilterExpression::Compare(self.0, CompareOp::Lt, val.into());
+    }
+
+    pub fn lt_eq<V: Into<FilterValue>>(self, val: V) -> FilterExpression {
+        return FilterExpression::Compare(self.0, CompareOp::LtEq, val.into());
+    }
+
+    pub fn gt<V: Into<FilterValue>>(self, val: V) -> FilterExpression {
+        return FilterExpression::Compare(self.0, CompareOp::Gt, val.into());
+    }
+
+    pub fn gt_eq<V: Into<FilterValue>>(self, val: V) -> FilterExpression {
+        return FilterExpression::Compare(self.0, CompareOp::GtEq, val.into());
+    }
+
+    pub fn is_in<V, I>(self, vals: I) -> FilterExpression
+    where
+        V: Into<FilterValue>,
+        I: IntoIterator<Item = V>,
+    {
+        return FilterExpression::In(self.0, vals.into_iter().map(|v| v.into()).collect());
+    }
+}
+
+/// Matches events of the given type, e.g. `begincast`
+pub fn event_type(ev_type: &str) -> FilterExpression {
+    return field(FilterField::Type).eq(ev_type);
+}
+
+/// Matches events during the given phase of an encounter
+pub fn encounter_phase(phase: i64) -> FilterExpression {
+    return field(FilterField::EncounterPhase).eq(phase);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expression_strings() {
+        let expr = field(FilterField::AbilityId)
+            .is_in(vec![18480, 18481])
+            .and(event_type("begincast"))
+            .and(
+                !field(FilterField::TargetName)
+                    .eq("Living \"Liquid\"")
+                    .or(encounter_phase(2)),
+            );
+        assert_eq!(
+            expr.to_string(),
+            r#"ability.id IN (18480, 18481) AND type = "begincast" AND NOT (target.name = "Living \"Liquid\"" OR encounterPhase = 2)"#
+        );
+        let range = FilterExpression::in_range(
+            event_type("applydebuff").and(field(FilterField::AbilityId).eq(1000)),
+            event_type("removedebuff"),
+        );
+        assert_eq!(
+            range.to_string(),
+            r#"IN RANGE FROM type = "applydebuff" AND ability.id = 1000 TO type = "removedebuff" END"#
+        );
+        assert_eq!(
+            serde_json::to_string(&event_type("death")).unwrap(),
+            r#""type = \"death\"""#
+        );
+    }
+
+    #[test]
+    fn test_empty_in_matches_nothing() {
+        let expr = field(FilterField::AbilityId).is_in(Vec::<i64>::new());
+        assert_eq!(expr.to_string(), "1 = 0");
+    }
+
+    #[test]
+    fn test_empty_and_or_are_constant() {
+        assert_eq!(FilterExpression::And(vec![]).to_string(), "1 = 1");
+        assert_eq!(
+            event_type("cast")
+                .or(FilterExpression::Or(vec![]))
+                .to_string(),
+            r#"type = "cast" OR (1 = 0)"#
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Combines the events from several views of a report into a single stream ordered by
+//! timestamp, for analyses which need e.g. casts, debuffs and deaths interleaved.
+use crate::fflogs_api::api::ApiError;
+use crate::fflogs_api::report::events::{
+    get_event_iterator, get_owned_event_stream, EventFilters, EventsStream, EventsView, ReportEvent,
+};
+use crate::fflogs_api::report::source::ReportDataSource;
+
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use std::pin::*;
+use std::sync::Arc;
+
+/// Opens a stream for each of the given views and merges them
+pub fn get_merged_event_iterator<'a>(
+    views: &[EventsView],
+    report_code: &'a str,
+    filters: EventFilters,
+    client: &'a dyn ReportDataSource,
+) -> MergedEventsStream<'a> {
+    let streams = views
+        .iter()
+        .map(|view| get_event_iterator(*view, report_code, filters.clone(), client))
+        .collect();
+    return MergedEventsStream::new(streams);
+}
+
+/// Opens an owned stream for each of the given views and merges them, giving a stream
+/// which can be moved into a spawned task
+pub fn get_owned_merged_event_stream(
+    views: &[EventsView],
+    report_code: String,
+    filters: EventFilters,
+    client: Arc<dyn ReportDataSource>,
+) -> MergedEventsStream<'static> {
+    let streams = views
+        .iter()
+        .map(|view| {
+            get_owned_event_stream(*view, report_code.clone(), filters.clone(), client.clone())
+        })
+        .collect();
+    return MergedEventsStream::new(streams);
+}
+
+/// A k-way merge of several event streams. Events which appear in more than one of the
+/// streams (such as a death showing up in both the deaths and summary views) are only
+/// returned once.
+pub struct MergedEventsStream<'a> {
+    streams: Vec<EventsStream<'a>>,
+    heads: Vec<Option<ReportEvent>>,
+    finished: Vec<bool>,
+    recent: Vec<EmittedEvent>,
+}
+
+/// An event which has already been returned at the current timestamp, along with the
+/// streams whose copies of it have since been discarded
+struct EmittedEvent {
+    stream: usize,
+    event: ReportEvent,
+    matched_streams: Vec<usize>,
+}
+
+impl<'a> MergedEventsStream<'a> {
+    pub fn new(streams: Vec<EventsStream<'a>>) -> Self {
+        let count = streams.len();
+        return MergedEventsStream {
+            streams: streams,
+            heads: (0..count).map(|_| None).collect(),
+            finished: vec![false; count],
+            recent: Vec::new(),
+        };
+    }
+
+    /// Checks whether an event from the given stream is a copy of one already returned
+    /// from another stream, marking that copy as used if so
+    fn is_duplicate(&mut self, stream: usize, event: &ReportEvent) -> bool {
+        let timestamp = event.get_timestamp();
+        //Streams are merged in timestamp order, so anything older can be forgotten
+        self.recent
+            .retain(|emitted| emitted.event.get_timestamp() == timestamp);
+        for emitted in self.recent.iter_mut() {
+            if emitted.stream != stream
+                && !emitted.matched_streams.contains(&stream)
+                && &emitted.event == event
+            {
+                emitted.matched_streams.push(stream);
+                return true;
+            }
+        }
+        self.recent.push(EmittedEvent {
+            stream: stream,
+            event: event.clone(),
+            matched_streams: Vec::new(),
+        });
+        return false;
+    }
+}
+
+impl<'a> Stream for MergedEventsStream<'a> {
+    type Item = Result<ReportEvent, ApiError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ReportEvent, ApiError>>> {
+        let this = &mut *self;
+        loop {
+            //We can only pick the earliest event once every stream has one ready
+            let mut waiting = false;
+            for idx in 0..this.streams.len() {
+                if this.heads[idx].is_some() || this.finished[idx] {
+                    continue;
+                }
+                match Pin::new(&mut this.streams[idx]).poll_next(cx) {
+                    Poll::Pending => waiting = true,
+                    Poll::Ready(None) => this.finished[idx] = true,
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(Some(Ok(ev))) => this.heads[idx] = Some(ev),
+                }
+            }
+            if waiting {
+                return Poll::Pending;
+            }
+            //Events without a timestamp are passed on as soon as they are seen
+            let earliest = this
+                .heads
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, head)| {
+                    head.as_ref()
+                        .map(|ev| (ev.get_timestamp().unwrap_or(0), idx))
+                })
+                .min();
+            let idx = match earliest {
+                None => return Poll::Ready(None),
+                Some((_, idx)) => idx,
+            };
+            let ev = this.heads[idx].take().unwrap();
+            if !this.is_duplicate(idx, &ev) {
+                return Poll::Ready(Some(Ok(ev)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::report::events::ReportEventsList;
+    use crate::fflogs_api::report::fights::ReportFightsList;
+    use futures::future::BoxFuture;
+    use futures::stream::StreamExt;
+    use serde_json::json;
+
+    struct ViewSource;
+
+    impl ReportDataSource for ViewSource {
+        fn fetch_fights<'a>(
+            &'a self,
+            _: &'a str,
+            _: bool,
+        ) -> BoxFuture<'a, Result<ReportFightsList, ApiError>> {
+            return Box::pin(futures::future::ready(Err(ApiError::NotImplementedError)));
+        }
+
+        fn fetch_events<'a>(
+            &'a self,
+            view: EventsView,
+            _: &'a str,
+            _: EventFilters,
+        ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> {
+            let events = match view {
+                EventsView::Casts => vec![
+                    json!({"timestamp": 10, "type": "a"}),
+                    json!({"timestamp": 30, "type": "shared"}),
+                    json!({"timestamp": 50, "type": "a"}),
+                ],
+                _ => vec![
+                    json!({"timestamp": 20, "type": "b"}),
+                    json!({"timestamp": 30, "type": "shared"}),
+                    json!({"timestamp": 30, "type": "b"}),
+                    json!({"timestamp": 40, "type": "b"}),
+                ],
+            };
+            let res = ReportEventsList {
+                events: events.into_iter().map(ReportEvent::from_raw).collect(),
+                next_page_timestamp: None,
+            };
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_merge_orders_and_dedupes() {
+        let filters = EventFilters {
+            start: 0,
+            end: 100,
+            ..Default::default()
+        };
+        let stream = get_merged_event_iterator(
+            &[EventsView::Casts, EventsView::Deaths],
+            "abcd",
+            filters,
+            &ViewSource,
+        );
+        let events: Vec<(u64, String)> = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(stream.collect::<Vec<_>>())
+            .into_iter()
+            .map(|ev| match ev.unwrap() {
+                ReportEvent::Unknown {
+                    event_type,
+                    timestamp,
+                    ..
+                } => (timestamp.unwrap(), event_type),
+                other => panic!("Unexpected event {:?}", other),
+            })
+            .collect();
+        let expected: Vec<(u64, String)> = vec![
+            (10, "a"),
+            (20, "b"),
+            (30, "shared"),
+            (30, "b"),
+            (40, "b"),
+            (50, "a"),
+        ]
+        .into_iter()
+        .map(|(ts, t)| (ts, t.to_string()))
+        .collect();
+        assert_eq!(events, expected);
+    }
+}
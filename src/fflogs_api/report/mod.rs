@@ -2,4 +2,7 @@
 //! FFLogs report
 pub mod events;
 pub mod fights;
+pub mod filter;
+pub mod merged;
+pub mod source;
 pub mod tables;
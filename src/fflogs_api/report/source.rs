@@ -0,0 +1,46 @@
+//! A common interface over the versions of the FFLogs API which can provide report
+//! data, so that analysis code does not need to care which one it is talking to.
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
+use crate::fflogs_api::report::events::{
+    request_events, EventFilters, EventsView, ReportEventsList,
+};
+use crate::fflogs_api::report::fights::{request_fights, ReportFightsList};
+
+use futures::future::BoxFuture;
+
+/// Something which can fetch the fights and events contained in a report
+pub trait ReportDataSource: Send + Sync {
+    /// Fetches the list of fights in a report along with its metadata
+    fn fetch_fights<'a>(
+        &'a self,
+        report_code: &'a str,
+        translate: bool,
+    ) -> BoxFuture<'a, Result<ReportFightsList, ApiError>>;
+
+    /// Fetches a single page of events from a report
+    fn fetch_events<'a>(
+        &'a self,
+        view: EventsView,
+        report_code: &'a str,
+        filters: EventFilters,
+    ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>>;
+}
+
+impl ReportDataSource for FFLogsApiClient {
+    fn fetch_fights<'a>(
+        &'a self,
+        report_code: &'a str,
+        translate: bool,
+    ) -> BoxFuture<'a, Result<ReportFightsList, ApiError>> {
+        return Box::pin(request_fights(report_code, translate, self));
+    }
+
+    fn fetch_events<'a>(
+        &'a self,
+        view: EventsView,
+        report_code: &'a str,
+        filters: EventFilters,
+    ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> {
+        return Box::pin(request_events(view, report_code, filters, self));
+    }
+}
@@ -1 +1,224 @@
+//! API calls and types for fetching the aggregated tables shown on a report's pages,
+//! such as the total damage done by each player over a fight or time range
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
+use crate::fflogs_api::report::events::EventFilters;
+use crate::fflogs_api::types::Ability;
 
+use http::uri::Uri;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use log::info;
+
+// //////////////////////////////// //
+// ////// Request Structs  //////// //
+// //////////////////////////////// //
+
+pub async fn request_table(
+    view: TableView,
+    report_code: &str,
+    filters: TableFilters,
+    client: &FFLogsApiClient,
+) -> Result<ReportTable, ApiError> {
+    info!(
+        "Making API request to report.tables endpoint for view {} on report code {}.",
+        view, report_code
+    );
+    let url = construct_url(view, report_code, filters, client)?;
+    let resp = client.run_report_request(url, report_code).await?;
+    let res: ReportTable =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+/// Attempts to construct the URL from which a request can be made to the report
+/// tables endpoint.
+pub fn construct_url(
+    view: TableView,
+    report_code: &str,
+    filters: TableFilters,
+    client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
+    let path = format!("/v1/report/tables/{}/{}", view, report_code);
+    let query = QueryParams {
+        filters: filters,
+        api_key: client.api_key().to_owned(),
+    };
+    return client.request_uri(&path, query);
+}
+
+/// The different tables which can be requested from the API
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TableView {
+    DamageDone,
+    DamageTaken,
+    Healing,
+    Casts,
+    Summons,
+    Buffs,
+    Debuffs,
+    Deaths,
+    Survivability,
+    Resources,
+    ResourcesGains,
+}
+
+impl fmt::Display for TableView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            TableView::DamageDone => write!(f, "damage-done"),
+            TableView::DamageTaken => write!(f, "damage-taken"),
+            TableView::Healing => write!(f, "healing"),
+            TableView::Casts => write!(f, "casts"),
+            TableView::Summons => write!(f, "summons"),
+            TableView::Buffs => write!(f, "buffs"),
+            TableView::Debuffs => write!(f, "debuffs"),
+            TableView::Deaths => write!(f, "deaths"),
+            TableView::Survivability => write!(f, "survivability"),
+            TableView::Resources => write!(f, "resources"),
+            TableView::ResourcesGains => write!(f, "resources-gains"),
+        }
+    }
+}
+
+/// How the entries of a table should be grouped
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum TableGrouping {
+    #[serde(rename = "source")]
+    Source,
+    #[serde(rename = "target")]
+    Target,
+    #[serde(rename = "ability")]
+    Ability,
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    #[serde(flatten)]
+    filters: TableFilters,
+    api_key: String,
+}
+
+/// Filters which may be applied to a table during the API request. These are the same
+/// as for the events endpoint, with the addition of the grouping to use.
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct TableFilters {
+    #[serde(flatten)]
+    pub events: EventFilters,
+    pub by: Option<TableGrouping>,
+}
+
+// //////////////////////////////// //
+// ////// Response Structs //////// //
+// //////////////////////////////// //
+
+/// A table returned by the API. Which of the fields are filled in depends on the view
+/// that was requested: buff and debuff tables contain auras, the others contain entries.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ReportTable {
+    #[serde(rename = "totalTime")]
+    pub total_time: Option<u64>,
+    #[serde(rename = "entries", default)]
+    pub entries: Vec<TableEntry>,
+    #[serde(rename = "auras", default)]
+    pub auras: Vec<TableAura>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TableEntry {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "id")]
+    pub id: Option<i64>,
+    #[serde(rename = "guid")]
+    pub guid: Option<i64>,
+    #[serde(rename = "type")]
+    pub entry_type: Option<String>,
+    #[serde(rename = "icon")]
+    pub icon: Option<String>,
+    #[serde(rename = "total")]
+    pub total: Option<f64>,
+    #[serde(rename = "activeTime")]
+    pub active_time: Option<u64>,
+    #[serde(rename = "timestamp")]
+    pub timestamp: Option<u64>,
+    #[serde(rename = "fight")]
+    pub fight: Option<i64>,
+    #[serde(rename = "killingBlow")]
+    pub killing_blow: Option<Ability>,
+    #[serde(rename = "abilities", default)]
+    pub abilities: Vec<TableBreakdown>,
+    #[serde(rename = "targets", default)]
+    pub targets: Vec<TableBreakdown>,
+}
+
+/// A breakdown of a table entry's total by ability or by target
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TableBreakdown {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "total")]
+    pub total: f64,
+    #[serde(rename = "type")]
+    pub breakdown_type: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TableAura {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "guid")]
+    pub guid: i64,
+    #[serde(rename = "type")]
+    pub ability_type: Option<i64>,
+    #[serde(rename = "abilityIcon")]
+    pub icon: Option<String>,
+    #[serde(rename = "totalUptime")]
+    pub total_uptime: Option<u64>,
+    #[serde(rename = "totalUses")]
+    pub total_uses: Option<u64>,
+    #[serde(rename = "bands", default)]
+    pub bands: Vec<AuraBand>,
+}
+
+/// A single period during which an aura was active
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AuraBand {
+    #[serde(rename = "startTime")]
+    pub start_time: u64,
+    #[serde(rename = "endTime")]
+    pub end_time: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::new_fflogs_api_client;
+
+    #[test]
+    fn test_parse_tables() {
+        let damage = r#"{"totalTime":600000,"entries":[{"name":"Kiiroi Yuki","id":1,"guid":1234,
+            "type":"WhiteMage","icon":"WhiteMage","total":4200000,"activeTime":590000,
+            "abilities":[{"name":"Glare","total":3000000,"type":1024}],"targets":[]}]}"#;
+        let table: ReportTable = serde_json::from_str(damage).unwrap();
+        assert_eq!(table.entries[0].total, Some(4200000.0));
+        assert_eq!(table.entries[0].abilities[0].name, "Glare");
+        assert!(table.auras.is_empty());
+
+        let buffs = r#"{"totalTime":600000,"auras":[{"name":"Medica II","guid":1000150,"type":8,
+            "abilityIcon":"012000-012523.png","totalUptime":45000,"totalUses":3,
+            "bands":[{"startTime":1000,"endTime":16000}]}]}"#;
+        let table: ReportTable = serde_json::from_str(buffs).unwrap();
+        assert_eq!(table.auras[0].bands[0].end_time, 16000);
+
+        let client = new_fflogs_api_client("key");
+        let filters = TableFilters {
+            by: Some(TableGrouping::Ability),
+            ..Default::default()
+        };
+        let url = construct_url(TableView::DamageDone, "abcd", filters, &client).unwrap();
+        assert_eq!(url.path(), "/v1/report/tables/damage-done/abcd");
+        assert!(url.query().unwrap().contains("by=ability"));
+    }
+}
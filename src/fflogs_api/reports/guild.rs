@@ -1 +1,79 @@
+//! API calls for listing the reports uploaded by a guild
+use crate::fflogs_api::api::{encode_path_segment, ApiError, FFLogsApiClient};
+use crate::fflogs_api::types::{ReportListFilters, ReportMetadata};
 
+use http::uri::Uri;
+
+use serde::Serialize;
+
+use log::info;
+
+pub async fn request_guild_reports(
+    guild_name: &str,
+    server: &str,
+    region: &str,
+    filters: ReportListFilters,
+    client: &FFLogsApiClient,
+) -> Result<Vec<ReportMetadata>, ApiError> {
+    info!(
+        "Making API request to reports.guild endpoint for {} on {} ({}).",
+        guild_name, server, region
+    );
+    let url = construct_url(guild_name, server, region, filters, client)?;
+    let resp = client.run_request(url).await?;
+    let res: Vec<ReportMetadata> =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+/// Attempts to construct the URL from which a request can be made to the guild
+/// reports endpoint.
+pub fn construct_url(
+    guild_name: &str,
+    server: &str,
+    region: &str,
+    filters: ReportListFilters,
+    client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
+    let path = format!(
+        "/v1/reports/guild/{}/{}/{}",
+        encode_path_segment(guild_name),
+        encode_path_segment(server),
+        encode_path_segment(region)
+    );
+    let query = QueryParams {
+        filters: filters,
+        api_key: client.api_key().to_owned(),
+    };
+    return client.request_uri(&path, query);
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    #[serde(flatten)]
+    filters: ReportListFilters,
+    api_key: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::api::new_fflogs_api_client;
+
+    #[test]
+    fn test_construct_url_and_parse() {
+        let client = new_fflogs_api_client("key");
+        let filters = ReportListFilters {
+            start: Some(1590000000000),
+            end: None,
+        };
+        let url = construct_url("Twin Tails", "Cerberus", "EU", filters, &client).unwrap();
+        assert_eq!(url.path(), "/v1/reports/guild/Twin%20Tails/Cerberus/EU");
+        assert_eq!(url.query(), Some("start=1590000000000&api_key=key"));
+
+        let msg = r#"[{"id":"aBcD1234eFgH5678","title":"TEA prog","owner":"Kiiroi Yuki","start":1590000000000,"end":1590010800000,"zone":887}]"#;
+        let reports: Vec<ReportMetadata> = serde_json::from_str(msg).unwrap();
+        assert_eq!(reports[0].code, "aBcD1234eFgH5678");
+        assert_eq!(reports[0].zone, 887);
+    }
+}
@@ -1,3 +1,3 @@
-//TODO
+//! API calls for listing the reports uploaded by a guild or user
 pub mod guild;
 pub mod user;
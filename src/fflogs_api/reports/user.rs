@@ -1 +1,47 @@
+//! API calls for listing the reports uploaded by a single user
+use crate::fflogs_api::api::{encode_path_segment, ApiError, FFLogsApiClient};
+use crate::fflogs_api::types::{ReportListFilters, ReportMetadata};
 
+use http::uri::Uri;
+
+use serde::Serialize;
+
+use log::info;
+
+pub async fn request_user_reports(
+    user_name: &str,
+    filters: ReportListFilters,
+    client: &FFLogsApiClient,
+) -> Result<Vec<ReportMetadata>, ApiError> {
+    info!(
+        "Making API request to reports.user endpoint for {}.",
+        user_name
+    );
+    let url = construct_url(user_name, filters, client)?;
+    let resp = client.run_request(url).await?;
+    let res: Vec<ReportMetadata> =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+/// Attempts to construct the URL from which a request can be made to the user
+/// reports endpoint.
+pub fn construct_url(
+    user_name: &str,
+    filters: ReportListFilters,
+    client: &FFLogsApiClient,
+) -> Result<Uri, ApiError> {
+    let path = format!("/v1/reports/user/{}", encode_path_segment(user_name));
+    let query = QueryParams {
+        filters: filters,
+        api_key: client.api_key().to_owned(),
+    };
+    return client.request_uri(&path, query);
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    #[serde(flatten)]
+    filters: ReportListFilters,
+    api_key: String,
+}
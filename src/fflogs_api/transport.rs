@@ -0,0 +1,40 @@
+//! The HTTP transport used by an FFLogsApiClient to actually send requests. This is
+//! abstracted behind a trait so that the API layer can be pointed at a local stand-in
+//! for the FFLogs servers during testing.
+use hyper::client::connect::{Connect, HttpConnector};
+use hyper::{Body, Client, Request, Response};
+use hyper_tls::HttpsConnector;
+
+use futures::future::BoxFuture;
+
+use super::api::ApiError;
+
+/// Something which can send an HTTP request and return the response
+pub trait HttpTransport: Send + Sync {
+    fn send(&self, request: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>>;
+}
+
+/// Transport which sends requests over the network using a hyper client
+pub struct HyperTransport<C> {
+    hyper_client: Client<C, Body>,
+}
+
+impl HyperTransport<HttpsConnector<HttpConnector>> {
+    /// Creates a transport which can send requests over either HTTPS or plain HTTP
+    pub fn new() -> Self {
+        let https = HttpsConnector::new();
+        return HyperTransport {
+            hyper_client: Client::builder().build::<_, Body>(https),
+        };
+    }
+}
+
+impl<C> HttpTransport for HyperTransport<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    fn send(&self, request: Request<Body>) -> BoxFuture<'_, Result<Response<Body>, ApiError>> {
+        let fut = self.hyper_client.request(request);
+        return Box::pin(async move { fut.await.map_err(|err| ApiError::RequestError(err)) });
+    }
+}
@@ -101,27 +101,27 @@ pub struct Resources {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Unit {
     #[serde(rename = "name")]
-    name: String,
+    pub name: String,
     #[serde(rename = "id")]
-    id: Option<i64>,
+    pub id: Option<i64>,
     #[serde(rename = "guid")]
-    guid: Option<i64>,
+    pub guid: Option<i64>,
     #[serde(rename = "type")]
-    unit_type: Option<String>,
+    pub unit_type: Option<String>,
     #[serde(rename = "server")]
-    server: Option<String>,
+    pub server: Option<String>,
     #[serde(rename = "icon")]
-    icon: Option<String>,
+    pub icon: Option<String>,
     #[serde(rename = "petOwner")]
-    pet_owner: Option<i64>,
+    pub pet_owner: Option<i64>,
     #[serde(rename = "fights")]
-    fights: Vec<FightLink>,
+    pub fights: Vec<FightLink>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct FightLink {
     #[serde(rename = "id")]
-    fight_id: i64,
+    pub fight_id: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -131,3 +131,56 @@ pub struct Instance {
     #[serde(rename = "phases")]
     phases: Option<Vec<String>>,
 }
+
+///The metric used to order rankings and parses
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RankingMetric {
+    #[serde(rename = "speed")]
+    Speed,
+    #[serde(rename = "execution")]
+    Execution,
+    #[serde(rename = "dps")]
+    Dps,
+    #[serde(rename = "hps")]
+    Hps,
+    #[serde(rename = "bossdps")]
+    BossDps,
+    #[serde(rename = "tankhps")]
+    TankHps,
+    #[serde(rename = "playerspeed")]
+    PlayerSpeed,
+}
+
+///Whether character rankings should cover only the current patch or all of them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RankingTimeframe {
+    #[serde(rename = "current")]
+    Current,
+    #[serde(rename = "historical")]
+    Historical,
+}
+
+///Summary of a report, as returned when listing the reports uploaded by a guild or user
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ReportMetadata {
+    #[serde(rename = "id")]
+    pub code: String,
+    #[serde(rename = "title")]
+    pub title: String,
+    #[serde(rename = "owner")]
+    pub owner: String,
+    #[serde(rename = "start")]
+    pub start: u64,
+    #[serde(rename = "end")]
+    pub end: u64,
+    #[serde(rename = "zone")]
+    pub zone: i64,
+}
+
+///Limits a list of reports to those which started within the given time range, in
+///milliseconds since the Unix epoch
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct ReportListFilters {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
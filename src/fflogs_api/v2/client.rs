@@ -0,0 +1,251 @@
+//! Authentication and query execution for the FFLogs v2 GraphQL API
+use crate::fflogs_api::api::{
+    new_fflogs_api_client_with_config, ApiClientConfig, ApiError, FFLogsApiClient,
+};
+use crate::fflogs_api::v2::report::V2MasterData;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use log::{debug, info, warn};
+
+pub const V2_TOKEN_PATH: &'static str = "/oauth/token";
+pub const V2_CLIENT_PATH: &'static str = "/api/v2/client";
+
+/// Tokens are refreshed this long before they are due to expire
+const TOKEN_REFRESH_MARGIN_SECS: u64 = 60;
+/// Number of reports whose master data is kept in memory
+const MAX_CACHED_MASTER_DATA: usize = 32;
+
+pub struct FFLogsV2Client {
+    http: FFLogsApiClient,
+    client_id: String,
+    client_secret: String,
+    token: tokio::sync::Mutex<Option<AccessToken>>,
+    master_data: Mutex<MasterDataCache>,
+}
+
+/// Master data for the most recently used reports, keyed by report code
+struct MasterDataCache {
+    entries: HashMap<String, (Arc<V2MasterData>, Instant)>,
+    capacity: usize,
+}
+
+impl MasterDataCache {
+    fn new(capacity: usize) -> Self {
+        return MasterDataCache {
+            entries: HashMap::new(),
+            capacity: capacity,
+        };
+    }
+
+    fn get(&mut self, report_code: &str) -> Option<Arc<V2MasterData>> {
+        let (data, last_access) = self.entries.get_mut(report_code)?;
+        *last_access = Instant::now();
+        return Some(data.clone());
+    }
+
+    /// Stores master data for a report, evicting the least recently used report if
+    /// the cache is full
+    fn insert(&mut self, report_code: &str, data: Arc<V2MasterData>) {
+        self.entries
+            .insert(report_code.to_string(), (data, Instant::now()));
+        while self.entries.len() > self.capacity {
+            let lru_code = match self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_access))| *last_access)
+            {
+                Some((code, _)) => code.clone(),
+                None => break,
+            };
+            debug!("Evicting master data for report {}", lru_code);
+            self.entries.remove(&lru_code);
+        }
+    }
+}
+
+struct AccessToken {
+    token: String,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(rename = "access_token")]
+    access_token: String,
+    #[serde(rename = "expires_in")]
+    expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct GraphQLRequest<'a, V> {
+    query: &'a str,
+    variables: V,
+}
+
+#[derive(Deserialize)]
+struct GraphQLResponse<T> {
+    data: Option<T>,
+    errors: Option<Vec<GraphQLErrorMessage>>,
+}
+
+#[derive(Deserialize)]
+struct GraphQLErrorMessage {
+    message: String,
+}
+
+impl FFLogsV2Client {
+    /// Runs a GraphQL query against the v2 API, deserializing the `data` field of the
+    /// response into the requested type.
+    pub async fn run_query<V, T>(&self, query: &str, variables: V) -> Result<T, ApiError>
+    where
+        V: Serialize,
+        T: DeserializeOwned,
+    {
+        let body = serde_json::to_string(&GraphQLRequest {
+            query: query,
+            variables: variables,
+        })
+        .map_err(|err| ApiError::QueryStringGenerationError(err.to_string()))?;
+        let uri = self
+            .http
+            .request_uri(V2_CLIENT_PATH, &[] as &[(&str, &str)])?;
+        let token = self.access_token().await?;
+        let resp = match self
+            .http
+            .run_post_request(uri.clone(), "application/json", Some(&token), body.clone())
+            .await
+        {
+            //Our token may have been revoked early, so get a new one and try once more
+            Err(ApiError::ApiReturnedError((401, _))) => {
+                warn!("FFLogs v2 API rejected our access token, requesting a new one");
+                self.invalidate_token().await;
+                let token = self.access_token().await?;
+                self.http
+                    .run_post_request(uri, "application/json", Some(&token), body)
+                    .await?
+            }
+            other => other?,
+        };
+        let parsed: GraphQLResponse<T> =
+            serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+        if let Some(errors) = parsed.errors {
+            if !errors.is_empty() {
+                return Err(ApiError::GraphQLError(
+                    errors.into_iter().map(|e| e.message).collect(),
+                ));
+            }
+        }
+        return parsed.data.ok_or(ApiError::GraphQLError(vec![
+            "Response contained no data".to_string()
+        ]));
+    }
+
+    /// Returns an authorization header value containing a valid access token,
+    /// requesting a new token if we don't have one or it is about to expire.
+    async fn access_token(&self) -> Result<String, ApiError> {
+        let mut token = self.token.lock().await;
+        let needs_refresh = match token.as_ref() {
+            None => true,
+            Some(t) => {
+                t.expires_at <= Instant::now() + Duration::from_secs(TOKEN_REFRESH_MARGIN_SECS)
+            }
+        };
+        if needs_refresh {
+            *token = Some(self.request_token().await?);
+        }
+        return Ok(format!("Bearer {}", token.as_ref().unwrap().token));
+    }
+
+    async fn invalidate_token(&self) {
+        *self.token.lock().await = None;
+    }
+
+    async fn request_token(&self) -> Result<AccessToken, ApiError> {
+        debug!("Requesting new access token for FFLogs v2 API");
+        let uri = self
+            .http
+            .request_uri(V2_TOKEN_PATH, &[] as &[(&str, &str)])?;
+        let credentials = base64::encode(format!("{}:{}", self.client_id, self.client_secret));
+        let resp = self
+            .http
+            .run_post_request(
+                uri,
+                "application/x-www-form-urlencoded",
+                Some(&format!("Basic {}", credentials)),
+                "grant_type=client_credentials".to_string(),
+            )
+            .await?;
+        let token: TokenResponse =
+            serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+        info!(
+            "Obtained FFLogs v2 access token valid for {}s",
+            token.expires_in
+        );
+        return Ok(AccessToken {
+            token: token.access_token,
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in),
+        });
+    }
+
+    pub(super) fn cached_master_data(&self, report_code: &str) -> Option<Arc<V2MasterData>> {
+        return self
+            .master_data
+            .lock()
+            .expect("Master data cache was poisoned")
+            .get(report_code);
+    }
+
+    pub(super) fn cache_master_data(&self, report_code: &str, data: Arc<V2MasterData>) {
+        self.master_data
+            .lock()
+            .expect("Master data cache was poisoned")
+            .insert(report_code, data);
+    }
+}
+
+/// Creates a new client for the v2 API using the given OAuth2 client credentials. The
+/// retry, rate limiting and transport options in the config are shared with the v1
+/// client.
+pub fn new_fflogs_v2_client(
+    client_id: &str,
+    client_secret: &str,
+    config: ApiClientConfig,
+) -> FFLogsV2Client {
+    info!(
+        "Created new FFLogs v2 API Client with client ID {}",
+        client_id
+    );
+    return FFLogsV2Client {
+        http: new_fflogs_api_client_with_config("", config),
+        client_id: client_id.to_owned(),
+        client_secret: client_secret.to_owned(),
+        token: tokio::sync::Mutex::new(None),
+        master_data: Mutex::new(MasterDataCache::new(MAX_CACHED_MASTER_DATA)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_master_data_cache_evicts_least_recently_used() {
+        let data = || -> Arc<V2MasterData> { Arc::new(serde_json::from_str("{}").unwrap()) };
+        let mut cache = MasterDataCache::new(2);
+        cache.insert("a", data());
+        std::thread::sleep(Duration::from_millis(2));
+        cache.insert("b", data());
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(cache.get("a").is_some());
+        cache.insert("c", data());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+}
@@ -0,0 +1,4 @@
+//! Client for version 2 of the FFLogs API, which is queried using GraphQL and
+//! authenticated using OAuth2 client credentials rather than a v1 api key.
+pub mod client;
+pub mod report;
@@ -0,0 +1,711 @@
+//! Typed GraphQL queries for the `reportData` section of the v2 API, along with the
+//! conversions needed to present their results in the same form as the v1 API.
+use crate::fflogs_api::api::ApiError;
+use crate::fflogs_api::report::events::{
+    decode_events, EventFilters, EventsView, Hostility, ReportEventsList,
+};
+use crate::fflogs_api::report::fights::{Fight, ReportFightsList};
+use crate::fflogs_api::report::source::ReportDataSource;
+use crate::fflogs_api::types::{FightLink, Unit};
+use crate::fflogs_api::v2::client::FFLogsV2Client;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use log::info;
+
+const REPORT_FIGHTS_QUERY: &'static str = r#"
+query ReportFights($code: String!, $translate: Boolean) {
+  reportData {
+    report(code: $code) {
+      title
+      startTime
+      endTime
+      owner { name }
+      zone { id }
+      fights(translate: $translate) {
+        id startTime endTime encounterID name difficulty size kill
+        bossPercentage fightPercentage lastPhase
+        gameZone { id name }
+        friendlyPlayers
+        enemyNPCs { id }
+        friendlyPets { id }
+        enemyPets { id }
+      }
+      masterData(translate: $translate) {
+        lang logVersion
+        actors { id gameID name type subType server icon petOwner }
+        abilities { gameID name icon type }
+      }
+    }
+  }
+}"#;
+
+const REPORT_MASTER_DATA_QUERY: &'static str = r#"
+query ReportMasterData($code: String!) {
+  reportData {
+    report(code: $code) {
+      masterData(translate: true) {
+        lang logVersion
+        actors { id gameID name type subType server icon petOwner }
+        abilities { gameID name icon type }
+      }
+    }
+  }
+}"#;
+
+const REPORT_EVENTS_QUERY: &'static str = r#"
+query ReportEvents(
+  $code: String!, $startTime: Float, $endTime: Float, $dataType: EventDataType,
+  $hostilityType: HostilityType, $sourceID: Int, $sourceInstanceID: Int,
+  $sourceClass: String, $targetID: Int, $targetInstanceID: Int, $targetClass: String,
+  $abilityID: Float, $death: Int, $encounterID: Int, $difficulty: Int,
+  $killType: KillType, $viewOptions: Int, $wipeCutoff: Int, $filterExpression: String,
+  $translate: Boolean
+) {
+  reportData {
+    report(code: $code) {
+      events(
+        startTime: $startTime, endTime: $endTime, dataType: $dataType,
+        hostilityType: $hostilityType, sourceID: $sourceID,
+        sourceInstanceID: $sourceInstanceID, sourceClass: $sourceClass,
+        targetID: $targetID, targetInstanceID: $targetInstanceID,
+        targetClass: $targetClass, abilityID: $abilityID, death: $death,
+        encounterID: $encounterID, difficulty: $difficulty, killType: $killType,
+        viewOptions: $viewOptions, wipeCutoff: $wipeCutoff,
+        filterExpression: $filterExpression, translate: $translate
+      ) {
+        data
+        nextPageTimestamp
+      }
+    }
+  }
+}"#;
+
+// //////////////////////////////// //
+// ////// Response Structs //////// //
+// //////////////////////////////// //
+
+#[derive(Deserialize, Debug)]
+struct ReportDataResponse<T> {
+    #[serde(rename = "reportData")]
+    report_data: ReportWrapper<T>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReportWrapper<T> {
+    #[serde(rename = "report")]
+    report: Option<T>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2Report {
+    #[serde(rename = "title")]
+    pub title: Option<String>,
+    #[serde(rename = "startTime")]
+    pub start_time: Option<f64>,
+    #[serde(rename = "endTime")]
+    pub end_time: Option<f64>,
+    #[serde(rename = "owner")]
+    pub owner: Option<V2User>,
+    #[serde(rename = "zone")]
+    pub zone: Option<V2Zone>,
+    #[serde(rename = "fights", default)]
+    pub fights: Vec<V2Fight>,
+    #[serde(rename = "masterData")]
+    pub master_data: Option<V2MasterData>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2User {
+    #[serde(rename = "name")]
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2Zone {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2Fight {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "startTime")]
+    pub start_time: f64,
+    #[serde(rename = "endTime")]
+    pub end_time: f64,
+    #[serde(rename = "encounterID")]
+    pub encounter_id: Option<i64>,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+    #[serde(rename = "difficulty")]
+    pub difficulty: Option<i64>,
+    #[serde(rename = "size")]
+    pub size: Option<i64>,
+    #[serde(rename = "kill")]
+    pub kill: Option<bool>,
+    #[serde(rename = "bossPercentage")]
+    pub boss_percentage: Option<f64>,
+    #[serde(rename = "fightPercentage")]
+    pub fight_percentage: Option<f64>,
+    #[serde(rename = "lastPhase")]
+    pub last_phase: Option<i64>,
+    #[serde(rename = "gameZone")]
+    pub game_zone: Option<V2Zone>,
+    #[serde(rename = "friendlyPlayers")]
+    pub friendly_players: Option<Vec<i64>>,
+    #[serde(rename = "enemyNPCs")]
+    pub enemy_npcs: Option<Vec<V2FightActor>>,
+    #[serde(rename = "friendlyPets")]
+    pub friendly_pets: Option<Vec<V2FightActor>>,
+    #[serde(rename = "enemyPets")]
+    pub enemy_pets: Option<Vec<V2FightActor>>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2FightActor {
+    #[serde(rename = "id")]
+    pub id: i64,
+}
+
+/// Report-wide data on the actors and abilities which appear in events
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2MasterData {
+    #[serde(rename = "lang")]
+    pub language: Option<String>,
+    #[serde(rename = "logVersion")]
+    pub log_version: Option<i32>,
+    #[serde(rename = "actors", default)]
+    pub actors: Vec<V2Actor>,
+    #[serde(rename = "abilities", default)]
+    pub abilities: Vec<V2Ability>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2Actor {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "gameID")]
+    pub game_id: Option<f64>,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub actor_type: String,
+    #[serde(rename = "subType")]
+    pub sub_type: Option<String>,
+    #[serde(rename = "server")]
+    pub server: Option<String>,
+    #[serde(rename = "icon")]
+    pub icon: Option<String>,
+    #[serde(rename = "petOwner")]
+    pub pet_owner: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct V2Ability {
+    #[serde(rename = "gameID")]
+    pub game_id: f64,
+    #[serde(rename = "name")]
+    pub name: Option<String>,
+    #[serde(rename = "icon")]
+    pub icon: Option<String>,
+    #[serde(rename = "type")]
+    pub ability_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct V2ReportEvents {
+    #[serde(rename = "events")]
+    events: V2EventsPage,
+}
+
+#[derive(Deserialize, Debug)]
+struct V2EventsPage {
+    #[serde(rename = "data")]
+    data: Vec<Value>,
+    #[serde(rename = "nextPageTimestamp")]
+    next_page_timestamp: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct V2ReportMasterData {
+    #[serde(rename = "masterData")]
+    master_data: V2MasterData,
+}
+
+// //////////////////////////////// //
+// ////// Request Structs  //////// //
+// //////////////////////////////// //
+
+#[derive(Serialize)]
+struct ReportEventsVariables<'a> {
+    code: &'a str,
+    #[serde(rename = "startTime")]
+    start_time: u64,
+    #[serde(rename = "endTime")]
+    end_time: u64,
+    #[serde(rename = "dataType")]
+    data_type: &'static str,
+    #[serde(rename = "hostilityType")]
+    hostility_type: Option<&'static str>,
+    #[serde(rename = "sourceID")]
+    source_id: Option<i64>,
+    #[serde(rename = "sourceInstanceID")]
+    source_instance: Option<i64>,
+    #[serde(rename = "sourceClass")]
+    source_class: Option<String>,
+    #[serde(rename = "targetID")]
+    target_id: Option<i64>,
+    #[serde(rename = "targetInstanceID")]
+    target_instance: Option<i64>,
+    #[serde(rename = "targetClass")]
+    target_class: Option<String>,
+    #[serde(rename = "abilityID")]
+    ability_id: Option<i64>,
+    death: Option<i64>,
+    #[serde(rename = "encounterID")]
+    encounter: Option<i64>,
+    difficulty: Option<i64>,
+    #[serde(rename = "killType")]
+    kill_type: Option<&'static str>,
+    #[serde(rename = "viewOptions")]
+    options: Option<i64>,
+    #[serde(rename = "wipeCutoff")]
+    cutoff: Option<i64>,
+    #[serde(rename = "filterExpression")]
+    filter: Option<String>,
+    translate: Option<bool>,
+}
+
+impl<'a> ReportEventsVariables<'a> {
+    fn new(view: EventsView, report_code: &'a str, filters: EventFilters) -> Self {
+        return ReportEventsVariables {
+            code: report_code,
+            start_time: filters.start,
+            end_time: filters.end,
+            data_type: event_data_type(view),
+            hostility_type: filters.hostility.map(|h| match h {
+                Hostility::Friendly => "Friendlies",
+                Hostility::Hostile => "Enemies",
+            }),
+            source_id: filters.source_id,
+            source_instance: filters.source_instance,
+            source_class: filters.source_class,
+            target_id: filters.target_id,
+            target_instance: filters.target_instance,
+            target_class: filters.target_class,
+            ability_id: filters.ability_id,
+            death: filters.death,
+            encounter: filters.encounter,
+            difficulty: filters.difficulty,
+            //v1 only returns wipes when this is set to 1
+            kill_type: match filters.wipes {
+                Some(1) => Some("Wipes"),
+                _ => None,
+            },
+            options: filters.options,
+            cutoff: filters.cutoff,
+            filter: filters.filter.map(|f| f.to_string()),
+            translate: filters.translate,
+        };
+    }
+}
+
+/// Maps a v1 events view onto the equivalent v2 `EventDataType`
+fn event_data_type(view: EventsView) -> &'static str {
+    match view {
+        EventsView::Summary => "All",
+        EventsView::DamageDone => "DamageDone",
+        EventsView::DamageTaken => "DamageTaken",
+        EventsView::Healing => "Healing",
+        EventsView::Casts => "Casts",
+        EventsView::Summons => "Summons",
+        EventsView::Buffs => "Buffs",
+        EventsView::Debuffs => "Debuffs",
+        EventsView::Deaths => "Deaths",
+        EventsView::Threat => "Threat",
+        EventsView::Resources => "Resources",
+        EventsView::Interrupts => "Interrupts",
+        EventsView::Dispels => "Dispels",
+    }
+}
+
+// //////////////////////////////// //
+// ///////// API Requests ///////// //
+// //////////////////////////////// //
+
+impl FFLogsV2Client {
+    /// Fetches the fights and master data for a report
+    pub async fn request_report(
+        &self,
+        report_code: &str,
+        translate: bool,
+    ) -> Result<V2Report, ApiError> {
+        info!(
+            "Making v2 API request for fights in report with code {}.",
+            report_code
+        );
+        let variables = json!({ "code": report_code, "translate": translate });
+        let resp: ReportDataResponse<V2Report> =
+            self.run_query(REPORT_FIGHTS_QUERY, variables).await?;
+        return resp
+            .report_data
+            .report
+            .ok_or_else(|| report_not_found(report_code));
+    }
+
+    /// Fetches the master data for a report, which is needed to fill in the details of
+    /// actors and abilities referred to by events
+    pub async fn request_master_data(
+        &self,
+        report_code: &str,
+    ) -> Result<Arc<V2MasterData>, ApiError> {
+        if let Some(data) = self.cached_master_data(report_code) {
+            return Ok(data);
+        }
+        let variables = json!({ "code": report_code });
+        let resp: ReportDataResponse<V2ReportMasterData> =
+            self.run_query(REPORT_MASTER_DATA_QUERY, variables).await?;
+        let data = Arc::new(
+            resp.report_data
+                .report
+                .ok_or_else(|| report_not_found(report_code))?
+                .master_data,
+        );
+        self.cache_master_data(report_code, data.clone());
+        return Ok(data);
+    }
+
+    /// Fetches a single page of events, converted into the same form as returned by
+    /// the v1 events endpoint
+    pub async fn request_events(
+        &self,
+        view: EventsView,
+        report_code: &str,
+        filters: EventFilters,
+    ) -> Result<ReportEventsList, ApiError> {
+        info!(
+            "Making v2 API request for events in report with code {}.",
+            report_code
+        );
+        let master_data = self.request_master_data(report_code).await?;
+        let variables = ReportEventsVariables::new(view, report_code, filters);
+        let resp: ReportDataResponse<V2ReportEvents> =
+            self.run_query(REPORT_EVENTS_QUERY, variables).await?;
+        let page = resp
+            .report_data
+            .report
+            .ok_or_else(|| report_not_found(report_code))?
+            .events;
+        let lookup = MasterDataLookup::new(&master_data);
+        let events: Vec<Value> = page
+            .data
+            .into_iter()
+            .map(|ev| lookup.normalise_event(ev))
+            .collect();
+        let events = decode_events(events);
+        return Ok(ReportEventsList {
+            events: events,
+            next_page_timestamp: page.next_page_timestamp.map(|ts| ts as u64),
+        });
+    }
+}
+
+impl ReportDataSource for FFLogsV2Client {
+    fn fetch_fights<'a>(
+        &'a self,
+        report_code: &'a str,
+        translate: bool,
+    ) -> BoxFuture<'a, Result<ReportFightsList, ApiError>> {
+        return Box::pin(async move {
+            let mut report = self.request_report(report_code, translate).await?;
+            if let Some(master_data) = report.master_data.take() {
+                self.cache_master_data(report_code, Arc::new(master_data));
+            }
+            let master_data = self.cached_master_data(report_code);
+            return Ok(report.into_fights_list(master_data.as_ref().map(|d| d.as_ref())));
+        });
+    }
+
+    fn fetch_events<'a>(
+        &'a self,
+        view: EventsView,
+        report_code: &'a str,
+        filters: EventFilters,
+    ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> {
+        return Box::pin(self.request_events(view, report_code, filters));
+    }
+}
+
+fn report_not_found(report_code: &str) -> ApiError {
+    return ApiError::GraphQLError(vec![format!("Report {} was not found", report_code)]);
+}
+
+// //////////////////////////////// //
+// ////////// Conversion ////////// //
+// //////////////////////////////// //
+
+impl V2Report {
+    /// Converts the report into the v1 representation of a fights list
+    pub fn into_fights_list(self, master_data: Option<&V2MasterData>) -> ReportFightsList {
+        let mut res = ReportFightsList {
+            fights: Vec::new(),
+            language: master_data.and_then(|d| d.language.clone()),
+            friendlies: Vec::new(),
+            enemies: Vec::new(),
+            friendly_pets: Vec::new(),
+            enemy_pets: Vec::new(),
+            phases: Vec::new(),
+            log_version: master_data.and_then(|d| d.log_version),
+            title: self.title,
+            owner: self.owner.map(|o| o.name),
+            start: self.start_time.map(|t| t as u64),
+            end: self.end_time.map(|t| t as u64),
+            zone: self.zone.map(|z| z.id),
+        };
+        let actors: &[V2Actor] = master_data.map_or(&[], |d| &d.actors);
+        let players: HashSet<i64> = actors
+            .iter()
+            .filter(|a| a.actor_type == "Player")
+            .map(|a| a.id)
+            .collect();
+        //Work out which fights each actor took part in
+        let mut actor_fights: HashMap<i64, Vec<FightLink>> = HashMap::new();
+        for fight in &self.fights {
+            let ids = fight.friendly_players.iter().flatten().cloned().chain(
+                vec![&fight.enemy_npcs, &fight.friendly_pets, &fight.enemy_pets]
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|a| a.id),
+            );
+            for id in ids {
+                actor_fights
+                    .entry(id)
+                    .or_insert_with(Vec::new)
+                    .push(FightLink { fight_id: fight.id });
+            }
+        }
+        for actor in actors {
+            let unit = Unit {
+                name: actor.name.clone(),
+                id: Some(actor.id),
+                guid: actor.game_id.map(|id| id as i64),
+                unit_type: if actor.actor_type == "Player" {
+                    actor.sub_type.clone()
+                } else {
+                    Some(actor.actor_type.clone())
+                },
+                server: actor.server.clone(),
+                icon: actor.icon.clone(),
+                pet_owner: actor.pet_owner,
+                fights: actor_fights.remove(&actor.id).unwrap_or_else(Vec::new),
+            };
+            match actor.actor_type.as_str() {
+                "Player" => res.friendlies.push(unit),
+                "Pet" if actor.pet_owner.map_or(false, |o| players.contains(&o)) => {
+                    res.friendly_pets.push(unit)
+                }
+                "Pet" => res.enemy_pets.push(unit),
+                "NPC" | "Boss" => res.enemies.push(unit),
+                _ => (),
+            }
+        }
+        res.fights = self
+            .fights
+            .into_iter()
+            .map(|f| Fight {
+                id: f.id,
+                start_time: f.start_time as u64,
+                end_time: f.end_time as u64,
+                boss: f.encounter_id,
+                name: f.name,
+                zone_id: f.game_zone.as_ref().map(|z| z.id),
+                zone_name: f.game_zone.and_then(|z| z.name),
+                size: f.size,
+                difficulty: f.difficulty,
+                kill: f.kill,
+                partial: None,
+                standard_composition: None,
+                //v1 gives percentages in hundredths of a percent
+                boss_percentage: f.boss_percentage.map(|p| (p * 100.0).round() as i32),
+                fight_percentage: f.fight_percentage.map(|p| (p * 100.0).round() as i64),
+                last_phase_for_percentage_display: f.last_phase,
+            })
+            .collect();
+        return res;
+    }
+}
+
+/// Lookup tables built from a report's master data, used to fill in the fields which
+/// v1 events contain but v2 events only refer to by ID
+struct MasterDataLookup<'a> {
+    friendly: HashSet<i64>,
+    abilities: HashMap<i64, &'a V2Ability>,
+}
+
+impl<'a> MasterDataLookup<'a> {
+    fn new(master_data: &'a V2MasterData) -> Self {
+        let players: HashSet<i64> = master_data
+            .actors
+            .iter()
+            .filter(|a| a.actor_type == "Player")
+            .map(|a| a.id)
+            .collect();
+        let friendly = master_data
+            .actors
+            .iter()
+            .filter(|a| {
+                players.contains(&a.id) || a.pet_owner.map_or(false, |o| players.contains(&o))
+            })
+            .map(|a| a.id)
+            .collect();
+        let abilities = master_data
+            .abilities
+            .iter()
+            .map(|a| (a.game_id as i64, a))
+            .collect();
+        return MasterDataLookup {
+            friendly: friendly,
+            abilities: abilities,
+        };
+    }
+
+    fn ability(&self, game_id: i64) -> Value {
+        let ability = self.abilities.get(&game_id);
+        return json!({
+            "name": ability.and_then(|a| a.name.clone()).unwrap_or_default(),
+            "guid": game_id,
+            "type": ability
+                .and_then(|a| a.ability_type.as_ref())
+                .and_then(|t| t.parse::<i64>().ok())
+                .unwrap_or(0),
+            "abilityIcon": ability.and_then(|a| a.icon.clone()),
+        });
+    }
+
+    /// Rewrites a v2 event into the shape of the equivalent v1 event
+    fn normalise_event(&self, mut event: Value) -> Value {
+        let obj: &mut Map<String, Value> = match event.as_object_mut() {
+            Some(obj) => obj,
+            None => return event,
+        };
+        for side in &["source", "target"] {
+            let friendly_key = format!("{}IsFriendly", side);
+            if obj.contains_key(&friendly_key) {
+                continue;
+            }
+            if let Some(id) = obj.get(&format!("{}ID", side)).and_then(|v| v.as_i64()) {
+                obj.insert(friendly_key, Value::Bool(self.friendly.contains(&id)));
+            }
+        }
+        for (v2_key, v1_key) in &[
+            ("abilityGameID", "ability"),
+            ("killingAbilityGameID", "killingAbility"),
+        ] {
+            if obj.contains_key(*v1_key) {
+                continue;
+            }
+            if let Some(id) = obj.get(*v2_key).and_then(|v| v.as_f64()) {
+                let ability = self.ability(id as i64);
+                obj.insert(v1_key.to_string(), ability);
+            }
+        }
+        return event;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::report::events::ReportEvent;
+
+    fn master_data() -> V2MasterData {
+        let msg = r#"{"lang":"en","logVersion":20,"actors":[
+            {"id":1,"gameID":0,"name":"Kiiroi Yuki","type":"Player","subType":"WhiteMage","server":"Cerberus","icon":"WhiteMage","petOwner":null},
+            {"id":2,"gameID":1,"name":"Eos","type":"Pet","subType":"Pet","server":null,"icon":"Pet","petOwner":1},
+            {"id":3,"gameID":8346,"name":"Living Liquid","type":"NPC","subType":"Boss","server":null,"icon":"Boss","petOwner":null}],
+            "abilities":[{"gameID":18480,"name":"Cascade","icon":"000000-000405.png","type":"1024"}]}"#;
+        return serde_json::from_str(msg).unwrap();
+    }
+
+    #[test]
+    fn test_normalise_cast_event() {
+        let data = master_data();
+        let lookup = MasterDataLookup::new(&data);
+        let v2_event: Value = serde_json::from_str(
+            r#"{"timestamp":1234,"type":"cast","sourceID":3,"targetID":1,"abilityGameID":18480,"fight":1}"#,
+        )
+        .unwrap();
+        let ev: ReportEvent = serde_json::from_value(lookup.normalise_event(v2_event)).unwrap();
+        match ev {
+            ReportEvent::Cast(cast) => {
+                assert_eq!(cast.timestamp, 1234);
+                assert_eq!(cast.ability.guid, 18480);
+                assert_eq!(cast.ability.name, "Cascade");
+                assert_eq!(cast.ability.ability_type, 1024);
+                assert_eq!(cast.source.is_friendly, false);
+                assert_eq!(cast.target.map(|t| t.is_friendly), Some(true));
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_report_into_fights_list() {
+        let msg = r#"{"title":"TEA prog","startTime":1590000000000,"endTime":1590003600000,
+            "owner":{"name":"Kiiroi Yuki"},"zone":{"id":887},
+            "fights":[{"id":1,"startTime":100,"endTime":60100,"encounterID":1050,
+                "name":"The Epic of Alexander","difficulty":100,"size":8,"kill":false,
+                "bossPercentage":42.4,"fightPercentage":87.6,"lastPhase":1,
+                "gameZone":{"id":887,"name":"The Epic of Alexander (Ultimate)"},
+                "friendlyPlayers":[1],"enemyNPCs":[{"id":3}],"friendlyPets":[{"id":2}],"enemyPets":[]}]}"#;
+        let report: V2Report = serde_json::from_str(msg).unwrap();
+        let data = master_data();
+        let fights = report.into_fights_list(Some(&data));
+        assert_eq!(fights.start, Some(1590000000000));
+        assert_eq!(fights.fights.len(), 1);
+        assert_eq!(fights.fights[0].start_time, 100);
+        assert_eq!(fights.fights[0].boss_percentage, Some(4240));
+        assert_eq!(fights.fights[0].fight_percentage, Some(8760));
+        assert_eq!(fights.friendlies.len(), 1);
+        assert_eq!(
+            fights.friendlies[0].unit_type,
+            Some("WhiteMage".to_string())
+        );
+        assert_eq!(fights.friendlies[0].fights, vec![FightLink { fight_id: 1 }]);
+        assert_eq!(fights.friendly_pets.len(), 1);
+        assert_eq!(fights.enemies.len(), 1);
+    }
+
+    #[test]
+    fn test_events_variables_keep_v1_filters() {
+        let filters = EventFilters {
+            options: Some(4),
+            cutoff: Some(2),
+            wipes: Some(1),
+            ..Default::default()
+        };
+        let variables = ReportEventsVariables::new(EventsView::Casts, "abcd", filters);
+        let json = serde_json::to_value(&variables).unwrap();
+        assert_eq!(json["viewOptions"], 4);
+        assert_eq!(json["wipeCutoff"], 2);
+        assert_eq!(json["killType"], "Wipes");
+
+        let filters = EventFilters {
+            wipes: Some(0),
+            ..Default::default()
+        };
+        let variables = ReportEventsVariables::new(EventsView::Casts, "abcd", filters);
+        let json = serde_json::to_value(&variables).unwrap();
+        assert_eq!(json["killType"], serde_json::Value::Null);
+    }
+}
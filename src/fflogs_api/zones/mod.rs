@@ -1,2 +1,2 @@
-//TODO
+//! API calls and types for the zones endpoint
 pub mod zones;
@@ -1 +1,133 @@
+//! API calls and types for fetching the list of zones and the encounters within them.
+//! Zones only change when new content is released, so the list is fetched once and
+//! then kept for the lifetime of the process.
+use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
 
+use std::sync::Arc;
+
+use http::uri::Uri;
+use lazy_static::*;
+use tokio::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use log::info;
+
+lazy_static! {
+    static ref ZONES: Mutex<Option<Arc<Vec<Zone>>>> = Mutex::new(None);
+}
+
+/// Returns the list of zones, only making a request to the API the first time it is
+/// called
+pub async fn get_zones(api_client: &FFLogsApiClient) -> Result<Arc<Vec<Zone>>, ApiError> {
+    let mut zones = ZONES.lock().await;
+    if let Some(cached) = zones.as_ref() {
+        return Ok(cached.clone());
+    }
+    let fetched = Arc::new(request_zones(api_client).await?);
+    *zones = Some(fetched.clone());
+    return Ok(fetched);
+}
+
+pub async fn request_zones(api_client: &FFLogsApiClient) -> Result<Vec<Zone>, ApiError> {
+    info!("Making API request to zones endpoint.");
+    let url = construct_url(api_client)?;
+    let resp = api_client.run_request(url).await?;
+    let res: Vec<Zone> =
+        serde_json::from_str(&resp).map_err(|err| ApiError::ResponseFormatError(err))?;
+    return Ok(res);
+}
+
+pub fn construct_url(api_client: &FFLogsApiClient) -> Result<Uri, ApiError> {
+    let query = QueryParams {
+        api_key: api_client.api_key().to_owned(),
+    };
+    return api_client.request_uri("/v1/zones", query);
+}
+
+/// Finds the encounter with the given ID, along with the zone it belongs to
+pub fn find_encounter(zones: &[Zone], encounter_id: i64) -> Option<(&Zone, &Encounter)> {
+    return zones.iter().find_map(|zone| {
+        zone.encounters
+            .iter()
+            .find(|enc| enc.id == encounter_id)
+            .map(|enc| (zone, enc))
+    });
+}
+
+#[derive(Serialize)]
+struct QueryParams {
+    api_key: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Zone {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "frozen")]
+    pub frozen: Option<bool>,
+    #[serde(rename = "encounters", default)]
+    pub encounters: Vec<Encounter>,
+    #[serde(rename = "brackets")]
+    pub brackets: Option<Brackets>,
+    #[serde(rename = "partitions", default)]
+    pub partitions: Vec<Partition>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Encounter {
+    #[serde(rename = "id")]
+    pub id: i64,
+    #[serde(rename = "name")]
+    pub name: String,
+}
+
+/// Describes how rankings within a zone are split up, e.g. by item level
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Brackets {
+    #[serde(rename = "min")]
+    pub min: f64,
+    #[serde(rename = "max")]
+    pub max: f64,
+    #[serde(rename = "bucket")]
+    pub bucket: f64,
+    #[serde(rename = "type")]
+    pub bracket_type: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct Partition {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "compact")]
+    pub compact: String,
+    #[serde(rename = "area")]
+    pub area: Option<i64>,
+    #[serde(rename = "default")]
+    pub default: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zones() {
+        let msg = r#"[{"id":887,"name":"The Epic of Alexander","frozen":false,
+            "encounters":[{"id":1050,"name":"The Epic of Alexander"}],
+            "brackets":{"min":1,"max":5,"bucket":1,"type":"Patch"},
+            "partitions":[{"name":"Standard","compact":"Standard","default":true}]},
+            {"id":29,"name":"Eden's Promise","frozen":false,
+            "encounters":[{"id":73,"name":"Cloud of Darkness"},{"id":74,"name":"Shadowkeeper"}],
+            "partitions":[]}]"#;
+        let zones: Vec<Zone> = serde_json::from_str(msg).unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].brackets.as_ref().unwrap().bracket_type, "Patch");
+        let (zone, enc) = find_encounter(&zones, 74).unwrap();
+        assert_eq!(zone.id, 29);
+        assert_eq!(enc.name, "Shadowkeeper");
+        assert!(find_encounter(&zones, 1).is_none());
+    }
+}
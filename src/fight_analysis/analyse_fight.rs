@@ -1,18 +1,23 @@
 use super::phase_definition::{
     load_definitions_files, DefinitionsLoadError, PhaseDefinitionsCollection, PhaseDefinitionsPhase,
 };
-use crate::fflogs_api::api::{new_fflogs_api_client, ApiError, FFLogsApiClient};
+use super::phase_scan::{create_actor_lookup, detect_phases, ActorLookup};
+use crate::fflogs_api::api::{new_fflogs_api_client_with_config, ApiClientConfig, ApiError};
 use crate::fflogs_api::report::events::ReportEvent;
-use crate::fflogs_api::report::fights::{request_fights, Fight, ReportFightsList};
+use crate::fflogs_api::report::fights::{Fight, ReportFightsList};
+use crate::fflogs_api::report::source::ReportDataSource;
 
 use lazy_static::*;
 use regex::Regex;
 
 use chrono::{DateTime, LocalResult, TimeZone, Utc};
 
+use futures::stream::{self, StreamExt, TryStreamExt};
+
 use log::debug;
 use std::clone::Clone;
 use std::convert::TryInto;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct ReportSummary {
@@ -195,9 +200,13 @@ pub fn get_pull_stats(raw_data: FightAnalysis, report_start_millis: u64) -> Figh
     }
 }
 
+/// Number of fights from a report which are analysed at once by default
+pub const DEFAULT_MAX_CONCURRENT_FIGHTS: usize = 4;
+
 pub struct LogAnalysisClient {
-    fflogs_api_client: FFLogsApiClient,
+    fflogs_api_client: Arc<dyn ReportDataSource>,
     phase_definitions: PhaseDefinitionsCollection,
+    max_concurrent_fights: usize,
 }
 
 impl LogAnalysisClient {
@@ -205,14 +214,39 @@ impl LogAnalysisClient {
         api_key: &str,
         definitions_dir: &str,
     ) -> Result<LogAnalysisClient, DefinitionsLoadError> {
-        let api = new_fflogs_api_client(api_key);
+        return LogAnalysisClient::new_with_config(api_key, definitions_dir, Default::default());
+    }
+
+    pub fn new_with_config(
+        api_key: &str,
+        definitions_dir: &str,
+        api_config: ApiClientConfig,
+    ) -> Result<LogAnalysisClient, DefinitionsLoadError> {
+        let api = new_fflogs_api_client_with_config(api_key, api_config);
+        return LogAnalysisClient::with_source(Arc::new(api), definitions_dir);
+    }
+
+    /// Creates an analysis client which fetches report data from the given source,
+    /// which may be either the v1 or v2 FFLogs API
+    pub fn with_source(
+        source: Arc<dyn ReportDataSource>,
+        definitions_dir: &str,
+    ) -> Result<LogAnalysisClient, DefinitionsLoadError> {
         let definitions = load_definitions_files(definitions_dir)?;
         let res = LogAnalysisClient {
-            fflogs_api_client: api,
+            fflogs_api_client: source,
             phase_definitions: definitions,
+            max_concurrent_fights: DEFAULT_MAX_CONCURRENT_FIGHTS,
         };
         return Ok(res);
     }
+
+    /// Sets how many fights from a single report may be analysed at once. Requests are
+    /// still subject to the API client's rate limits however many fights are in progress.
+    pub fn with_max_concurrent_fights(mut self, max_concurrent_fights: usize) -> Self {
+        self.max_concurrent_fights = max_concurrent_fights;
+        return self;
+    }
 }
 
 #[derive(Debug)]
@@ -259,10 +293,25 @@ pub async fn analyse_fights_by_name<'a>(
     name: String,
     analysis_client: &'a LogAnalysisClient,
 ) -> Result<ReportAnalysis<'a>, AnalysisError> {
-    analyse_fights_from_report(
+    analyse_fights_by_name_with_progress(report_code, name, analysis_client, |_, _| ()).await
+}
+
+/// Analyses fights with the given name, calling `on_progress` with the number of fights
+/// analysed so far and the total number of fights each time a fight is finished.
+pub async fn analyse_fights_by_name_with_progress<'a, F>(
+    report_code: String,
+    name: String,
+    analysis_client: &'a LogAnalysisClient,
+    on_progress: F,
+) -> Result<ReportAnalysis<'a>, AnalysisError>
+where
+    F: Fn(usize, usize),
+{
+    analyse_fights_from_report_with_progress(
         report_code,
         |f| f.name == Some(name.clone()),
         analysis_client,
+        on_progress,
     )
     .await
 }
@@ -275,14 +324,36 @@ pub async fn analyse_fights_from_report<'a, P>(
 where
     P: Fn(&Fight) -> bool,
 {
-    let client = &analysis_client.fflogs_api_client;
+    analyse_fights_from_report_with_progress(report_code, pred, analysis_client, |_, _| ()).await
+}
+
+/// Analyses every fight in a report matching the predicate, with up to the client's
+/// `max_concurrent_fights` fights in progress at once. Fights are returned in pull order
+/// regardless of the order in which their analyses finish.
+pub async fn analyse_fights_from_report_with_progress<'a, P, F>(
+    report_code: String,
+    pred: P,
+    analysis_client: &'a LogAnalysisClient,
+    on_progress: F,
+) -> Result<ReportAnalysis<'a>, AnalysisError>
+where
+    P: Fn(&Fight) -> bool,
+    F: Fn(usize, usize),
+{
+    let client = analysis_client.fflogs_api_client.as_ref();
     let definitions = &analysis_client.phase_definitions;
-    let mut fights: Vec<FightAnalysis> = Vec::new();
-    let report_fights: ReportFightsList = request_fights(&report_code, true, &client)
+    let report_fights: ReportFightsList = client
+        .fetch_fights(&report_code, true)
         .await
         .map_err(|e| AnalysisError::ApiError(e))?;
     let matching_fights: Vec<&Fight> = report_fights.fights.iter().filter(|&f| pred(f)).collect();
-    for fight in matching_fights.iter() {
+
+    let actors = create_actor_lookup(&report_fights.enemies);
+    let actors = &actors;
+
+    //Check that every fight can be analysed before sending any requests
+    let mut pending = Vec::new();
+    for (idx, fight) in matching_fights.iter().enumerate() {
         let fight_name = fight
             .name
             .as_ref()
@@ -297,15 +368,34 @@ where
             start_time: fight.start_time,
             end_time: fight.end_time,
         };
-        let fight_analysis: FightAnalysis = analyse_fight(
-            fight.start_time,
-            fight.end_time,
-            phase_definitions,
-            &client,
-            &metadata,
-        )
-        .await?;
-        fights.push(fight_analysis);
+        pending.push(async move {
+            let res = analyse_fight(
+                metadata.start_time,
+                metadata.end_time,
+                phase_definitions,
+                actors,
+                client,
+                &metadata,
+            )
+            .await;
+            return res.map(|analysis| (idx, analysis));
+        });
+    }
+
+    let total = pending.len();
+    debug!(
+        "Analysing {} fights from report {} with up to {} at once",
+        total, report_code, analysis_client.max_concurrent_fights
+    );
+    on_progress(0, total);
+    let mut analyses =
+        stream::iter(pending).buffer_unordered(analysis_client.max_concurrent_fights.max(1));
+    let mut fights: Vec<Option<FightAnalysis>> = (0..total).map(|_| None).collect();
+    let mut finished = 0;
+    while let Some((idx, fight_analysis)) = analyses.try_next().await? {
+        fights[idx] = Some(fight_analysis);
+        finished += 1;
+        on_progress(finished, total);
     }
     let res = ReportAnalysis {
         report_code: report_code,
@@ -315,7 +405,7 @@ where
         report_end: report_fights
             .end
             .ok_or(AnalysisError::UnspecifiedFightTime)?,
-        fights: fights,
+        fights: fights.into_iter().flatten().collect(),
     };
     return Ok(res);
 }
@@ -334,6 +424,14 @@ pub enum AnalysisError {
 impl std::fmt::Display for AnalysisError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let msg = match self {
+            AnalysisError::ApiError(ApiError::RetriesExhausted((attempts, last)))
+                if last.is_throttled() =>
+            {
+                format!(
+                    "The FFLogs API is rate limiting us, gave up after {} attempts. Please try again later.",
+                    attempts
+                )
+            }
             AnalysisError::ApiError(e) => format!(
                 "Something went wrong when communicating with the FFLogs API: {:?}",
                 e
@@ -363,28 +461,24 @@ async fn analyse_fight<'a>(
     start_time: u64,
     end_time: u64,
     definitions: &'a Vec<PhaseDefinitionsPhase>,
-    client: &FFLogsApiClient,
+    actors: &ActorLookup,
+    client: &dyn ReportDataSource,
     metadata: &FightData,
 ) -> Result<FightAnalysis<'a>, AnalysisError> {
-    let mut analysed_phases: Vec<RawPhaseData> = Vec::new();
-    let mut latest_time_processed = start_time;
-
-    for phase_definition in definitions.iter() {
-        let phase_analysis: Option<RawPhaseData> = analyse_phase(
-            latest_time_processed,
-            end_time,
-            phase_definition,
-            client,
-            metadata,
-        )
-        .await?;
-        if let Some(phase) = phase_analysis {
-            latest_time_processed = phase.phase_end.unwrap_or(phase.phase_start);
-            analysed_phases.push(phase);
-        } else {
-            break;
-        }
-    }
+    debug!(
+        "Now analysing phases for a fight in report {}.",
+        metadata.report_code
+    );
+    let mut analysed_phases: Vec<RawPhaseData> = detect_phases(
+        definitions,
+        &metadata.report_code,
+        start_time,
+        end_time,
+        actors,
+        client,
+    )
+    .await
+    .map_err(|e| AnalysisError::ApiError(e))?;
     let mut phases_iter = analysed_phases.iter_mut().peekable();
     loop {
         let cur_phase: &mut RawPhaseData;
@@ -415,50 +509,6 @@ async fn analyse_fight<'a>(
     return Ok(res);
 }
 
-async fn analyse_phase(
-    start_time: u64,
-    end_time: u64,
-    definition: &PhaseDefinitionsPhase,
-    client: &FFLogsApiClient,
-    metadata: &FightData,
-) -> Result<Option<RawPhaseData>, AnalysisError> {
-    let report_code = metadata.report_code.clone();
-    debug!(
-        "Now analysing phase {} for a fight in report {}.",
-        &definition.phase_name, report_code
-    );
-    let mut res: RawPhaseData = Default::default();
-    res.phase_name = definition.phase_name.clone();
-    if let Some(marker) = &definition.start_marker {
-        let matching_event: Option<ReportEvent> = marker
-            .get_matching_event(report_code.clone(), start_time, end_time, client.clone())
-            .await
-            .map_err(|e| AnalysisError::ApiError(e))?;
-        match matching_event {
-            None => return Ok(None),
-            Some(ev) => res.phase_start_event = ev,
-        };
-        res.phase_start = res
-            .phase_start_event
-            .get_timestamp()
-            .ok_or(AnalysisError::InvalidEventMatchError)?;
-    };
-
-    if let Some(marker) = &definition.end_marker {
-        let matching_event: Option<ReportEvent> = marker
-            .get_matching_event(report_code.clone(), res.phase_start, end_time, client)
-            .await
-            .map_err(|e| AnalysisError::ApiError(e))?;
-        res.phase_end_event = matching_event;
-        res.phase_end = res
-            .phase_end_event
-            .as_ref()
-            .and_then(|ev| ev.get_timestamp());
-    };
-
-    return Ok(Some(res));
-}
-
 struct FightData {
     name: String,
     report_code: String,
@@ -486,9 +536,84 @@ pub struct FightAnalysis<'a> {
 
 #[derive(Default, Debug)]
 pub struct RawPhaseData {
-    phase_name: String,
-    phase_start: u64,
-    phase_start_event: ReportEvent,
-    phase_end: Option<u64>,
-    phase_end_event: Option<ReportEvent>,
+    pub(super) phase_name: String,
+    pub(super) phase_start: u64,
+    pub(super) phase_start_event: ReportEvent,
+    pub(super) phase_end: Option<u64>,
+    pub(super) phase_end_event: Option<ReportEvent>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::report::events::{EventFilters, EventsView, ReportEventsList};
+    use futures::future::BoxFuture;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Serves a report of UwU pulls, taking longer to answer for earlier pulls so that
+    /// their analyses finish out of order
+    struct PullsSource;
+
+    impl ReportDataSource for PullsSource {
+        fn fetch_fights<'a>(
+            &'a self,
+            _: &'a str,
+            _: bool,
+        ) -> BoxFuture<'a, Result<ReportFightsList, ApiError>> {
+            let fights: Vec<_> = (0..5)
+                .map(|i| {
+                    json!({"id": i + 1, "start_time": i * 10000, "end_time": i * 10000 + 5000,
+                        "boss": 1, "name": "The Ultima Weapon"})
+                })
+                .collect();
+            let res = serde_json::from_value(json!({
+                "fights": fights, "friendlies": [], "enemies": [], "friendlyPets": [],
+                "enemyPets": [], "phases": [], "start": 0, "end": 50000
+            }))
+            .unwrap();
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+
+        fn fetch_events<'a>(
+            &'a self,
+            _: EventsView,
+            _: &'a str,
+            filters: EventFilters,
+        ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> {
+            return Box::pin(async move {
+                let delay = 50 - filters.start / 1000;
+                tokio::time::delay_for(std::time::Duration::from_millis(delay)).await;
+                let ev = json!({"timestamp": filters.start, "type": "encounterstart",
+                    "encounterID": 1, "name": null, "difficulty": null, "size": null});
+                return Ok(ReportEventsList {
+                    events: vec![serde_json::from_value(ev).unwrap()],
+                    next_page_timestamp: None,
+                });
+            });
+        }
+    }
+
+    #[test]
+    fn test_concurrent_analysis_keeps_pull_order() {
+        let client = LogAnalysisClient::with_source(Arc::new(PullsSource), "phaseidentifiers")
+            .unwrap()
+            .with_max_concurrent_fights(3);
+        let progress = Mutex::new(Vec::new());
+        let analysis = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(analyse_fights_by_name_with_progress(
+                "abcd".to_string(),
+                "The Ultima Weapon".to_string(),
+                &client,
+                |done, total| progress.lock().unwrap().push((done, total)),
+            ))
+            .unwrap();
+        let starts: Vec<u64> = analysis.fights.iter().map(|f| f.start_time).collect();
+        assert_eq!(starts, vec![0, 10000, 20000, 30000, 40000]);
+        assert_eq!(
+            progress.into_inner().unwrap(),
+            vec![(0, 5), (1, 5), (2, 5), (3, 5), (4, 5), (5, 5)]
+        );
+    }
 }
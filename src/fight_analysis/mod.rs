@@ -1,2 +1,3 @@
 pub mod analyse_fight;
 pub mod phase_definition;
+pub mod phase_scan;
@@ -1,16 +1,12 @@
-use crate::fflogs_api::api::{ApiError, FFLogsApiClient};
-use crate::fflogs_api::report::events::{
-    get_event_iterator, EventFilters, EventsView, Hostility, ReportEvent,
-};
+use crate::fflogs_api::report::events::{EventFilters, EventsView, Hostility, ReportEvent};
+use crate::fflogs_api::report::filter::{event_type, field, FilterExpression, FilterField};
+use crate::fflogs_api::types::{Ability, Source, Target};
 use serde::{Deserialize, Serialize};
 
 use std::collections::HashMap;
 
 use log::trace;
 
-use futures::future;
-use futures::stream::Stream;
-use futures::TryStreamExt;
 use std::fs::{read_dir, File};
 use std::io::Read;
 use toml;
@@ -93,45 +89,135 @@ pub struct PhaseDefinitionsPhase {
     #[serde(rename = "name")]
     pub phase_name: String,
     #[serde(rename = "startMarker")]
-    pub start_marker: Option<PhaseMarker>,
+    pub start_marker: Option<BoundaryMarker>,
     #[serde(rename = "endMarker")]
-    pub end_marker: Option<PhaseMarker>,
+    pub end_marker: Option<BoundaryMarker>,
+}
+
+/// The marker for the start or end of a phase, along with optional timing rules. The
+/// `notBeforeMs` and `notAfterMs` windows are relative to the start of the previous
+/// phase for start markers, or of the phase being ended for end markers.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct BoundaryMarker {
+    #[serde(flatten)]
+    pub marker: PhaseMarker,
+    /// Moves the phase boundary this long after the matching event
+    #[serde(rename = "offsetMs")]
+    pub offset_ms: Option<u64>,
+    #[serde(rename = "notBeforeMs")]
+    pub not_before_ms: Option<u64>,
+    #[serde(rename = "notAfterMs")]
+    pub not_after_ms: Option<u64>,
+}
+
+impl BoundaryMarker {
+    /// The time of the phase boundary marked by the given event
+    pub fn boundary_time(&self, ev_time: u64) -> u64 {
+        return ev_time + self.offset_ms.unwrap_or(0);
+    }
+
+    /// Whether an event at the given time may match, for a marker whose windows are
+    /// relative to `reference_time`
+    pub fn in_window(&self, ev_time: u64, reference_time: u64) -> bool {
+        let not_before = self
+            .not_before_ms
+            .map_or(true, |ms| ev_time >= reference_time + ms);
+        return not_before && !self.window_closed(ev_time, reference_time);
+    }
+
+    /// Whether the window has closed by the given time, so that no later event can match
+    pub fn window_closed(&self, ev_time: u64, reference_time: u64) -> bool {
+        return self
+            .not_after_ms
+            .map_or(false, |ms| ev_time > reference_time + ms);
+    }
 }
 
 impl PhaseMarker {
-    pub fn check_event(&self, ev: &ReportEvent) -> bool {
+    /// Checks whether this event completes the marker. Composite markers record their
+    /// progress towards a match in `state`, which should be created with
+    /// `MarkerState::new` and passed every event in turn.
+    pub fn check_event(&self, ev: &ReportEvent, state: &mut MarkerState) -> bool {
         match self {
             PhaseMarker::FightStartMarker => true,
             PhaseMarker::EventMarker(marker) => marker.compare_to_event(ev),
+            PhaseMarker::AnyOf(marker) => {
+                //Every alternative sees the event, so nested composites keep their progress
+                let mut res = false;
+                for (child, child_state) in marker.markers.iter().zip(state.children.iter_mut()) {
+                    res |= child.check_event(ev, child_state);
+                }
+                res
+            }
+            PhaseMarker::AllOf(marker) => {
+                for (idx, child) in marker.markers.iter().enumerate() {
+                    if !state.matched[idx] && child.check_event(ev, &mut state.children[idx]) {
+                        state.matched[idx] = true;
+                    }
+                }
+                state.matched.iter().all(|m| *m)
+            }
+            PhaseMarker::Sequence(marker) => marker.check_event(ev, state),
         }
     }
 
+    /// Composite markers are searched for using the summary view, which contains every
+    /// type of event
     pub fn create_event_filters(&self) -> (EventsView, EventFilters) {
         match self {
             PhaseMarker::FightStartMarker => (EventsView::Summary, Default::default()),
             PhaseMarker::EventMarker(marker) => marker.create_event_filters(),
+            PhaseMarker::AnyOf(_) | PhaseMarker::AllOf(_) | PhaseMarker::Sequence(_) => {
+                let mut res: EventFilters = Default::default();
+                let filters: Vec<Option<FilterExpression>> = self
+                    .event_requests()
+                    .into_iter()
+                    .map(|(_, filters)| filters.filter)
+                    .collect();
+                //A single unfiltered child means every event must be fetched
+                if filters.iter().all(|f| f.is_some()) {
+                    res.filter = filters
+                        .into_iter()
+                        .flatten()
+                        .fold(None, |acc, f| match acc {
+                            None => Some(f),
+                            Some(a) => Some(a.or(f)),
+                        });
+                }
+                (EventsView::Summary, res)
+            }
         }
     }
 
-    pub async fn get_matching_event(
-        &self,
-        report_code: String,
-        start_time: u64,
-        end_time: u64,
-        client: &FFLogsApiClient,
-    ) -> Result<Option<ReportEvent>, ApiError> {
+    /// The event requests needed to find this marker whilst scanning through a fight.
+    /// The fight start marker needs none, as it is simply the first event in the fight.
+    pub fn event_requests(&self) -> Vec<(EventsView, EventFilters)> {
         match self {
-            PhaseMarker::EventMarker(e) => {
-                e.get_matching_event(report_code, start_time, end_time, client)
-                    .await
-            }
-            PhaseMarker::FightStartMarker => {
-                let (view, mut filters) = self.create_event_filters();
-                filters.start = start_time;
-                filters.end = end_time;
-                let mut events_stream = get_event_iterator(view, &report_code, filters, &client);
-                events_stream.try_next().await
-            }
+            PhaseMarker::FightStartMarker => Vec::new(),
+            PhaseMarker::EventMarker(marker) => vec![marker.create_event_filters()],
+            PhaseMarker::AnyOf(_) | PhaseMarker::AllOf(_) | PhaseMarker::Sequence(_) => self
+                .children()
+                .iter()
+                .flat_map(|child| child.event_requests())
+                .collect(),
+        }
+    }
+
+    /// The number of matching events which should be skipped before this marker is
+    /// considered to have been reached
+    pub fn instance_no(&self) -> i32 {
+        match self {
+            PhaseMarker::EventMarker(marker) => marker.instance_no(),
+            _ => 0,
+        }
+    }
+
+    /// The markers which make up a composite marker
+    pub fn children(&self) -> &[PhaseMarker] {
+        match self {
+            PhaseMarker::AnyOf(marker) | PhaseMarker::AllOf(marker) => &marker.markers,
+            PhaseMarker::Sequence(marker) => &marker.markers,
+            _ => &[],
         }
     }
 }
@@ -143,6 +229,87 @@ pub enum PhaseMarker {
     FightStartMarker,
     #[serde(rename = "event")]
     EventMarker(EventMarker),
+    #[serde(rename = "anyOf")]
+    AnyOf(CompositeMarker),
+    #[serde(rename = "allOf")]
+    AllOf(CompositeMarker),
+    #[serde(rename = "sequence")]
+    Sequence(SequenceMarker),
+}
+
+/// Marker made up of several other markers, matching on the first event to satisfy any
+/// (for `anyOf`) or all (for `allOf`) of them
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct CompositeMarker {
+    #[serde(rename = "markers")]
+    markers: Vec<PhaseMarker>,
+}
+
+/// Marker matching each of its markers in order, optionally requiring each to follow
+/// the last within a number of milliseconds
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct SequenceMarker {
+    #[serde(rename = "markers")]
+    markers: Vec<PhaseMarker>,
+    #[serde(rename = "withinMs")]
+    within_ms: Option<u64>,
+}
+
+impl SequenceMarker {
+    fn check_event(&self, ev: &ReportEvent, state: &mut MarkerState) -> bool {
+        if state.next_step >= self.markers.len() {
+            return true;
+        }
+        let timestamp = ev.get_timestamp();
+        //Give up on a partial match once the next step is overdue
+        if let (Some(within), Some(last), Some(ts)) = (self.within_ms, state.last_match, timestamp)
+        {
+            if ts > last + within {
+                *state = MarkerState::for_children(&self.markers);
+            }
+        }
+        let step = state.next_step;
+        if self.markers[step].check_event(ev, &mut state.children[step]) {
+            state.next_step += 1;
+            state.last_match = timestamp;
+            return state.next_step == self.markers.len();
+        }
+        //A repeat of the first step restarts the sequence from that event
+        if step > 0 {
+            let mut first_state = MarkerState::new(&self.markers[0]);
+            if self.markers[0].check_event(ev, &mut first_state) {
+                *state = MarkerState::for_children(&self.markers);
+                state.children[0] = first_state;
+                state.next_step = 1;
+                state.last_match = timestamp;
+            }
+        }
+        return false;
+    }
+}
+
+/// Progress made towards matching a marker, which is only needed by composite markers
+#[derive(Debug, Clone)]
+pub struct MarkerState {
+    children: Vec<MarkerState>,
+    matched: Vec<bool>,
+    next_step: usize,
+    last_match: Option<u64>,
+}
+
+impl MarkerState {
+    pub fn new(marker: &PhaseMarker) -> Self {
+        return MarkerState::for_children(marker.children());
+    }
+
+    fn for_children(markers: &[PhaseMarker]) -> Self {
+        return MarkerState {
+            children: markers.iter().map(MarkerState::new).collect(),
+            matched: vec![false; markers.len()],
+            next_step: 0,
+            last_match: None,
+        };
+    }
 }
 
 impl EventMarker {
@@ -218,9 +385,100 @@ impl EventMarker {
                     false
                 }
             }
+            EventMarker::Targetable(marker) => match ev {
+                ReportEvent::TargetabilityUpdate(ev_data) => {
+                    let actor_matches = ev_data
+                        .source
+                        .source_data
+                        .as_ref()
+                        .map_or(false, |actor| actor.guid == marker.actor_id);
+                    let res = actor_matches && ev_data.is_targetable() == marker.targetable;
+                    if !res {
+                        trace!(
+                            "Discarded TargetabilityUpdate event for marker {:?} due to non-matching data: {:?}",
+                            self,
+                            ev
+                        );
+                    } else {
+                        trace!("Accepted event {:?} as match for marker {:?}", ev, self);
+                    };
+                    res
+                }
+                _ => self.log_type_mismatch(ev, "TargetabilityUpdate"),
+            },
+            EventMarker::HpThreshold(marker) => match ev {
+                ReportEvent::Damage(ev_data) => {
+                    self.log_hp_match(ev, marker.matches(ev_data.target.as_ref()))
+                }
+                _ => self.log_type_mismatch(ev, "Damage"),
+            },
+            EventMarker::ApplyBuff(marker) => match ev {
+                ReportEvent::ApplyBuff(ev_data) => self.log_status_match(
+                    ev,
+                    marker.matches(&ev_data.source, &ev_data.target, &ev_data.ability),
+                ),
+                _ => self.log_type_mismatch(ev, "ApplyBuff"),
+            },
+            EventMarker::RemoveBuff(marker) => match ev {
+                ReportEvent::RemoveBuff(ev_data) => self.log_status_match(
+                    ev,
+                    marker.matches(&ev_data.source, &ev_data.target, &ev_data.ability),
+                ),
+                _ => self.log_type_mismatch(ev, "RemoveBuff"),
+            },
+            EventMarker::ApplyDebuff(marker) => match ev {
+                ReportEvent::ApplyDebuff(ev_data) => self.log_status_match(
+                    ev,
+                    marker.matches(&ev_data.source, &ev_data.target, &ev_data.ability),
+                ),
+                _ => self.log_type_mismatch(ev, "ApplyDebuff"),
+            },
+            EventMarker::RemoveDebuff(marker) => match ev {
+                ReportEvent::RemoveDebuff(ev_data) => self.log_status_match(
+                    ev,
+                    marker.matches(&ev_data.source, &ev_data.target, &ev_data.ability),
+                ),
+                _ => self.log_type_mismatch(ev, "RemoveDebuff"),
+            },
         }
     }
 
+    fn log_hp_match(&self, ev: &ReportEvent, res: bool) -> bool {
+        if !res {
+            trace!(
+                "Discarded Damage event for marker {:?} as target is above the HP threshold: {:?}",
+                self,
+                ev
+            );
+        } else {
+            trace!("Accepted event {:?} as match for marker {:?}", ev, self);
+        };
+        return res;
+    }
+
+    fn log_status_match(&self, ev: &ReportEvent, res: bool) -> bool {
+        if !res {
+            trace!(
+                "Discarded status event for marker {:?} due to non-matching data: {:?}",
+                self,
+                ev
+            );
+        } else {
+            trace!("Accepted event {:?} as match for marker {:?}", ev, self);
+        };
+        return res;
+    }
+
+    fn log_type_mismatch(&self, ev: &ReportEvent, expected: &str) -> bool {
+        trace!(
+            "Discarded event for marker {:?} due to non-matching event_type (Expected {}): {:?}",
+            self,
+            expected,
+            ev
+        );
+        return false;
+    }
+
     pub fn create_event_filters(&self) -> (EventsView, EventFilters) {
         let mut res: EventFilters = Default::default();
         let view: EventsView;
@@ -228,51 +486,77 @@ impl EventMarker {
             EventMarker::BeginCast(marker) => {
                 res.ability_id = Some(marker.ability_id);
                 res.hostility = Some(marker.hostility.unwrap_or(Hostility::Hostile));
+                //The casts view also contains completed casts, which we don't want
+                res.filter = Some(
+                    event_type("begincast")
+                        .and(field(FilterField::AbilityId).eq(marker.ability_id)),
+                );
                 view = EventsView::Casts;
             }
             EventMarker::EndCast(marker) => {
                 res.ability_id = Some(marker.ability_id);
                 res.hostility = Some(marker.hostility.unwrap_or(Hostility::Hostile));
+                res.filter = Some(
+                    event_type("cast").and(field(FilterField::AbilityId).eq(marker.ability_id)),
+                );
                 view = EventsView::Casts;
             }
             EventMarker::Death(marker) => {
                 res.target_id = Some(marker.target_id);
                 res.hostility = Some(marker.hostility.unwrap_or(Hostility::Hostile));
+                res.filter = Some(
+                    event_type("death").and(field(FilterField::TargetId).eq(marker.target_id)),
+                );
                 view = EventsView::Deaths;
             }
+            EventMarker::Targetable(marker) => {
+                res.hostility = Some(marker.hostility.unwrap_or(Hostility::Hostile));
+                //The actor is matched by game ID once the events have been fetched, as
+                //the report only refers to it by its ID within the report
+                res.filter = Some(event_type("targetabilityupdate"));
+                view = EventsView::Summary;
+            }
+            EventMarker::HpThreshold(marker) => {
+                res.target_id = Some(marker.target_id);
+                //Damage done to the boss comes from the party
+                res.hostility = Some(marker.hostility.unwrap_or(Hostility::Friendly));
+                res.filter = Some(
+                    event_type("damage").and(field(FilterField::TargetId).eq(marker.target_id)),
+                );
+                view = EventsView::DamageDone;
+            }
+            EventMarker::ApplyBuff(marker) => {
+                res = marker.create_event_filters("applybuff");
+                view = EventsView::Buffs;
+            }
+            EventMarker::RemoveBuff(marker) => {
+                res = marker.create_event_filters("removebuff");
+                view = EventsView::Buffs;
+            }
+            EventMarker::ApplyDebuff(marker) => {
+                res = marker.create_event_filters("applydebuff");
+                view = EventsView::Debuffs;
+            }
+            EventMarker::RemoveDebuff(marker) => {
+                res = marker.create_event_filters("removedebuff");
+                view = EventsView::Debuffs;
+            }
         }
         return (view, res);
     }
-    async fn choose_from_matching(
-        &self,
-        mut events: impl Stream<Item = Result<ReportEvent, ApiError>> + Unpin,
-    ) -> Result<Option<ReportEvent>, ApiError> {
-        let mut skip_count = match self {
+
+    pub fn instance_no(&self) -> i32 {
+        match self {
             EventMarker::BeginCast(m) => m.instance_no.unwrap_or(0),
             EventMarker::EndCast(m) => m.instance_no.unwrap_or(0),
             EventMarker::Death(m) => m.instance_no.unwrap_or(0),
-        };
-        while skip_count > 0 {
-            let _ = events.try_next().await?;
-            skip_count -= 1;
+            EventMarker::Targetable(m) => m.instance_no.unwrap_or(0),
+            EventMarker::HpThreshold(m) => m.instance_no.unwrap_or(0),
+            EventMarker::ApplyBuff(m) => m.instance_no.unwrap_or(0),
+            EventMarker::RemoveBuff(m) => m.instance_no.unwrap_or(0),
+            EventMarker::ApplyDebuff(m) => m.instance_no.unwrap_or(0),
+            EventMarker::RemoveDebuff(m) => m.instance_no.unwrap_or(0),
         }
-        return events.try_next().await;
-    }
-
-    pub async fn get_matching_event(
-        &self,
-        report_code: String,
-        start_time: u64,
-        end_time: u64,
-        client: &FFLogsApiClient,
-    ) -> Result<Option<ReportEvent>, ApiError> {
-        let (view, mut filters) = self.create_event_filters();
-        filters.start = start_time;
-        filters.end = end_time;
-        let events_stream = get_event_iterator(view, &report_code, filters, &client);
-        let matches = events_stream.try_filter(|ev| future::ready(self.compare_to_event(ev)));
-        let chosen = self.choose_from_matching(matches).await;
-        return chosen;
     }
 }
 
@@ -285,6 +569,18 @@ pub enum EventMarker {
     EndCast(EndCastMarker),
     #[serde(rename = "Death")]
     Death(DeathMarker),
+    #[serde(rename = "Targetable")]
+    Targetable(TargetableMarker),
+    #[serde(rename = "HpThreshold")]
+    HpThreshold(HpThresholdMarker),
+    #[serde(rename = "ApplyBuff")]
+    ApplyBuff(StatusMarker),
+    #[serde(rename = "RemoveBuff")]
+    RemoveBuff(StatusMarker),
+    #[serde(rename = "ApplyDebuff")]
+    ApplyDebuff(StatusMarker),
+    #[serde(rename = "RemoveDebuff")]
+    RemoveDebuff(StatusMarker),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -314,3 +610,222 @@ pub struct DeathMarker {
     #[serde(rename = "eventHostility")]
     hostility: Option<Hostility>,
 }
+/// Marker for an actor becoming targetable or untargetable, e.g. a boss leaving the
+/// arena between phases. The actor is identified by its game ID.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct TargetableMarker {
+    #[serde(rename = "actorId")]
+    actor_id: i64,
+    #[serde(rename = "targetable")]
+    targetable: bool,
+    #[serde(rename = "instanceNo")]
+    instance_no: Option<i32>,
+    #[serde(rename = "eventHostility")]
+    hostility: Option<Hostility>,
+}
+
+/// Marker for the first damage event which leaves the target at or below the given
+/// percentage of its maximum HP
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct HpThresholdMarker {
+    #[serde(rename = "targetId")]
+    target_id: i64,
+    #[serde(rename = "hpPercent")]
+    hp_percent: f64,
+    #[serde(rename = "instanceNo")]
+    instance_no: Option<i32>,
+    #[serde(rename = "eventHostility")]
+    hostility: Option<Hostility>,
+}
+
+impl HpThresholdMarker {
+    fn matches(&self, target: Option<&Target>) -> bool {
+        let target = match target {
+            Some(t) if t.get_id() == Some(self.target_id) => t,
+            _ => return false,
+        };
+        let resources = match &target.resources {
+            Some(r) => r,
+            None => return false,
+        };
+        return match (resources.hp, resources.max_hp) {
+            (Some(hp), Some(max_hp)) if max_hp > 0 => {
+                hp as f64 * 100.0 / max_hp as f64 <= self.hp_percent
+            }
+            _ => false,
+        };
+    }
+}
+
+/// Marker for a buff or debuff being applied or removed, optionally restricted to a
+/// particular source or target actor
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct StatusMarker {
+    #[serde(rename = "abilityId")]
+    ability_id: i64,
+    #[serde(rename = "sourceId")]
+    source_id: Option<i64>,
+    #[serde(rename = "targetId")]
+    target_id: Option<i64>,
+    #[serde(rename = "instanceNo")]
+    instance_no: Option<i32>,
+    #[serde(rename = "eventHostility")]
+    hostility: Option<Hostility>,
+}
+
+impl StatusMarker {
+    fn matches(&self, source: &Source, target: &Option<Target>, ability: &Ability) -> bool {
+        let source_matches = self
+            .source_id
+            .map_or(true, |id| source.get_id() == Some(id));
+        let target_matches = self.target_id.map_or(true, |id| {
+            target.as_ref().and_then(|t| t.get_id()) == Some(id)
+        });
+        return ability.guid == self.ability_id && source_matches && target_matches;
+    }
+
+    fn create_event_filters(&self, ev_type: &str) -> EventFilters {
+        let mut res: EventFilters = Default::default();
+        res.ability_id = Some(self.ability_id);
+        res.source_id = self.source_id;
+        res.target_id = self.target_id;
+        res.hostility = Some(self.hostility.unwrap_or(Hostility::Hostile));
+        //The buffs and debuffs views also contain refreshes and stack changes
+        let mut filter = event_type(ev_type).and(field(FilterField::AbilityId).eq(self.ability_id));
+        if let Some(id) = self.source_id {
+            filter = filter.and(field(FilterField::SourceId).eq(id));
+        }
+        if let Some(id) = self.target_id {
+            filter = filter.and(field(FilterField::TargetId).eq(id));
+        }
+        res.filter = Some(filter);
+        return res;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check(marker: &PhaseMarker, ev: &ReportEvent) -> bool {
+        return marker.check_event(ev, &mut MarkerState::new(marker));
+    }
+
+    #[test]
+    fn test_debuff_marker() {
+        let marker: PhaseMarker = toml::from_str(
+            r#"
+            type = "event"
+            evType = "ApplyDebuff"
+            abilityId = 1234
+            targetId = 7
+            eventHostility = 0
+            "#,
+        )
+        .unwrap();
+        let (view, filters) = marker.create_event_filters();
+        assert_eq!(view, EventsView::Debuffs);
+        assert_eq!(
+            filters.filter.unwrap().to_string(),
+            r#"type = "applydebuff" AND ability.id = 1234 AND target.id = 7"#
+        );
+
+        let debuff = |ev_type: &str, target: i64| -> ReportEvent {
+            serde_json::from_value(json!({"timestamp": 100, "type": ev_type,
+                "sourceID": 3, "sourceIsFriendly": false, "targetID": target, "targetIsFriendly": true,
+                "ability": {"name": "Doom", "guid": 1234, "type": 1, "abilityIcon": null}}))
+            .unwrap()
+        };
+        assert!(check(&marker, &debuff("applydebuff", 7)));
+        assert!(!check(&marker, &debuff("applydebuff", 8)));
+        assert!(!check(&marker, &debuff("removedebuff", 7)));
+    }
+
+    #[test]
+    fn test_hp_threshold_marker() {
+        let marker: PhaseMarker = toml::from_str(
+            r#"
+            type = "event"
+            evType = "HpThreshold"
+            targetId = 12
+            hpPercent = 65.0
+            "#,
+        )
+        .unwrap();
+        let (view, filters) = marker.create_event_filters();
+        assert_eq!(view, EventsView::DamageDone);
+        assert_eq!(filters.hostility, Some(Hostility::Friendly));
+
+        let hit = |target: i64, hp: i64| -> ReportEvent {
+            serde_json::from_value(json!({"timestamp": 100, "type": "damage",
+                "sourceID": 1, "sourceIsFriendly": true, "targetID": target, "targetIsFriendly": false,
+                "targetResources": {"hitPoints": hp, "maxHitPoints": 1000},
+                "ability": {"name": "Attack", "guid": 7, "type": 128, "abilityIcon": null}}))
+            .unwrap()
+        };
+        assert!(!check(&marker, &hit(12, 651)));
+        assert!(check(&marker, &hit(12, 650)));
+        assert!(!check(&marker, &hit(13, 100)));
+    }
+
+    fn cast(ts: u64, id: i64) -> ReportEvent {
+        return serde_json::from_value(json!({"timestamp": ts, "type": "cast",
+            "sourceID": 5, "sourceIsFriendly": false,
+            "ability": {"name": "", "guid": id, "type": 1024, "abilityIcon": null}}))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_composite_markers() {
+        let definition: PhaseDefinitionsPhase = toml::from_str(
+            r#"
+            name = "Perfect Alexander"
+            [startMarker]
+            type = "sequence"
+            withinMs = 1000
+                [[startMarker.markers]]
+                type = "anyOf"
+                    [[startMarker.markers.markers]]
+                    type = "event"
+                    evType = "Cast"
+                    abilityId = 1
+                    [[startMarker.markers.markers]]
+                    type = "event"
+                    evType = "Cast"
+                    abilityId = 2
+                [[startMarker.markers]]
+                type = "event"
+                evType = "Cast"
+                abilityId = 3
+            [endMarker]
+            type = "allOf"
+                [[endMarker.markers]]
+                type = "event"
+                evType = "Cast"
+                abilityId = 4
+                [[endMarker.markers]]
+                type = "event"
+                evType = "Cast"
+                abilityId = 5
+            "#,
+        )
+        .unwrap();
+
+        let start = definition.start_marker.unwrap().marker;
+        assert_eq!(start.event_requests().len(), 3);
+        let mut state = MarkerState::new(&start);
+        //The second step comes too late after the first, but a repeat restarts the window
+        assert!(!start.check_event(&cast(0, 2), &mut state));
+        assert!(!start.check_event(&cast(1500, 3), &mut state));
+        assert!(!start.check_event(&cast(2000, 1), &mut state));
+        assert!(!start.check_event(&cast(2500, 1), &mut state));
+        assert!(start.check_event(&cast(3200, 3), &mut state));
+
+        let end = definition.end_marker.unwrap().marker;
+        let mut state = MarkerState::new(&end);
+        assert!(!end.check_event(&cast(100, 5), &mut state));
+        assert!(!end.check_event(&cast(200, 5), &mut state));
+        assert!(end.check_event(&cast(300, 4), &mut state));
+    }
+}
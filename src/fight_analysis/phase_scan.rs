@@ -0,0 +1,710 @@
+//! Detects the phases of a fight in a single pass over its events. One request is made
+//! for each distinct view needed by the markers in a fight's phase definitions, and the
+//! resulting streams are merged so the phases can be assigned by a state machine as the
+//! events go past.
+use super::analyse_fight::RawPhaseData;
+use super::phase_definition::{BoundaryMarker, MarkerState, PhaseDefinitionsPhase, PhaseMarker};
+use crate::fflogs_api::api::ApiError;
+use crate::fflogs_api::report::events::{
+    get_event_iterator, EventFilters, EventsView, ReportEvent,
+};
+use crate::fflogs_api::report::merged::MergedEventsStream;
+use crate::fflogs_api::report::source::ReportDataSource;
+use crate::fflogs_api::types::{ActorData, Unit};
+
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+
+use log::{debug, trace};
+
+/// Details of the actors in a report, keyed by their ID within the report
+pub type ActorLookup = HashMap<i64, ActorData>;
+
+pub fn create_actor_lookup(units: &[Unit]) -> ActorLookup {
+    return units
+        .iter()
+        .filter_map(|unit| {
+            let id = unit.id?;
+            let actor = ActorData {
+                name: unit.name.clone(),
+                id: id,
+                guid: unit.guid.unwrap_or(id),
+                actor_type: unit.unit_type.clone().unwrap_or_default(),
+                icon: unit.icon.clone(),
+            };
+            return Some((id, actor));
+        })
+        .collect();
+}
+
+/// Finds the start and end of each phase in the given time range. Phases are detected
+/// in order, and scanning stops at the first phase whose start can't be found.
+pub async fn detect_phases(
+    definitions: &[PhaseDefinitionsPhase],
+    report_code: &str,
+    start_time: u64,
+    end_time: u64,
+    actors: &ActorLookup,
+    client: &dyn ReportDataSource,
+) -> Result<Vec<RawPhaseData>, ApiError> {
+    let uses_fight_start = definitions
+        .iter()
+        .filter_map(|phase| phase.start_marker.as_ref())
+        .any(|boundary| boundary.marker == PhaseMarker::FightStartMarker);
+    let fight_start_event = if uses_fight_start {
+        let filters = EventFilters {
+            start: start_time,
+            end: end_time,
+            ..Default::default()
+        };
+        get_event_iterator(EventsView::Summary, report_code, filters, client)
+            .with_read_ahead(0)
+            .try_next()
+            .await?
+    } else {
+        None
+    };
+
+    let mut scanner = PhaseScanner::new(definitions, fight_start_event, start_time);
+    let mut events = create_scan_stream(definitions, report_code, start_time, end_time, client);
+    while !scanner.is_finished() {
+        match events.try_next().await? {
+            None => break,
+            Some(mut ev) => {
+                fill_actor_data(&mut ev, actors);
+                scanner.feed(&ev);
+            }
+        }
+    }
+    return Ok(scanner.into_phases());
+}
+
+/// Events from the v1 API only refer to actors by their ID within the report, so fill in
+/// the rest of their details for markers which match on an actor's game ID
+fn fill_actor_data(ev: &mut ReportEvent, actors: &ActorLookup) {
+    if let ReportEvent::TargetabilityUpdate(ev_data) = ev {
+        if ev_data.source.source_data.is_none() {
+            ev_data.source.source_data = ev_data.source.id.and_then(|id| actors.get(&id).cloned());
+        }
+    }
+}
+
+/// Opens a stream covering the events needed by every marker in the definitions. Markers
+/// which use the same view and hostility share a single request, with their filter
+/// expressions combined. Markers without a filter expression can't be described by a
+/// combined request, so keep requests of their own.
+fn create_scan_stream<'a>(
+    definitions: &[PhaseDefinitionsPhase],
+    report_code: &'a str,
+    start_time: u64,
+    end_time: u64,
+    client: &'a dyn ReportDataSource,
+) -> MergedEventsStream<'a> {
+    let markers = definitions
+        .iter()
+        .flat_map(|phase| phase.start_marker.iter().chain(phase.end_marker.iter()));
+    let requests = combine_requests(markers.flat_map(|boundary| boundary.marker.event_requests()));
+    debug!(
+        "Scanning events for phase markers using {} requests",
+        requests.len()
+    );
+    let streams = requests
+        .into_iter()
+        .map(|(view, mut filters)| {
+            filters.start = start_time;
+            filters.end = end_time;
+            get_event_iterator(view, report_code, filters, client)
+        })
+        .collect();
+    return MergedEventsStream::new(streams);
+}
+
+fn combine_requests(
+    marker_requests: impl Iterator<Item = (EventsView, EventFilters)>,
+) -> Vec<(EventsView, EventFilters)> {
+    let mut requests: Vec<(EventsView, EventFilters)> = Vec::new();
+    for (view, filters) in marker_requests {
+        let filter = match filters.filter {
+            Some(ref filter) => filter.clone(),
+            None => {
+                requests.push((view, filters));
+                continue;
+            }
+        };
+        let existing = requests
+            .iter_mut()
+            .find(|(v, f)| *v == view && f.hostility == filters.hostility && f.filter.is_some());
+        match existing {
+            None => requests.push((view, filters)),
+            Some((_, existing)) => {
+                let merged = match existing.filter.take() {
+                    Some(a) if a == filter => a,
+                    Some(a) => a.or(filter),
+                    None => filter,
+                };
+                *existing = EventFilters {
+                    hostility: filters.hostility,
+                    filter: Some(merged),
+                    translate: filters.translate,
+                    ..Default::default()
+                };
+            }
+        }
+    }
+    return requests;
+}
+
+/// Keeps track of how many matching events a marker has seen so far
+struct MarkerMatcher<'d> {
+    boundary: &'d BoundaryMarker,
+    state: MarkerState,
+    skip_remaining: i32,
+    reference_time: u64,
+    /// Events before this time fall before the previous boundary, which may have been
+    /// moved later by an offset
+    earliest_time: u64,
+}
+
+impl<'d> MarkerMatcher<'d> {
+    fn new(boundary: &'d BoundaryMarker, reference_time: u64, earliest_time: u64) -> Self {
+        return MarkerMatcher {
+            boundary: boundary,
+            state: MarkerState::new(&boundary.marker),
+            skip_remaining: boundary.marker.instance_no(),
+            reference_time: reference_time,
+            earliest_time: earliest_time,
+        };
+    }
+
+    fn feed(&mut self, ev: &ReportEvent, ev_time: u64) -> bool {
+        if ev_time < self.earliest_time {
+            return false;
+        }
+        if !self.boundary.marker.check_event(ev, &mut self.state) {
+            return false;
+        }
+        //Events outside the marker's window don't count towards its instance number
+        if !self.boundary.in_window(ev_time, self.reference_time) {
+            trace!(
+                "Discarded event {:?} as it fell outside the marker's window",
+                ev
+            );
+            self.state = MarkerState::new(&self.boundary.marker);
+            return false;
+        }
+        if self.skip_remaining > 0 {
+            self.skip_remaining -= 1;
+            self.state = MarkerState::new(&self.boundary.marker);
+            return false;
+        }
+        return true;
+    }
+
+    fn window_closed(&self, ev_time: u64) -> bool {
+        return self.boundary.window_closed(ev_time, self.reference_time);
+    }
+}
+
+enum ScanState<'d> {
+    AwaitingStart(usize, MarkerMatcher<'d>),
+    /// Waiting for the end of a phase, whilst also watching for the start of the next
+    /// phase in case the end is never found
+    AwaitingEnd(usize, MarkerMatcher<'d>, Option<MarkerMatcher<'d>>),
+    Finished,
+}
+
+struct PhaseScanner<'d> {
+    definitions: &'d [PhaseDefinitionsPhase],
+    fight_start_event: Option<ReportEvent>,
+    latest_time: u64,
+    /// Start of the most recent phase, which marker windows are relative to
+    phase_start_time: u64,
+    phases: Vec<RawPhaseData>,
+    state: ScanState<'d>,
+}
+
+impl<'d> PhaseScanner<'d> {
+    fn new(
+        definitions: &'d [PhaseDefinitionsPhase],
+        fight_start_event: Option<ReportEvent>,
+        start_time: u64,
+    ) -> Self {
+        let mut res = PhaseScanner {
+            definitions: definitions,
+            fight_start_event: fight_start_event,
+            latest_time: start_time,
+            phase_start_time: start_time,
+            phases: Vec::new(),
+            state: ScanState::Finished,
+        };
+        res.begin_phase(0);
+        return res;
+    }
+
+    fn is_finished(&self) -> bool {
+        match self.state {
+            ScanState::Finished => true,
+            _ => false,
+        }
+    }
+
+    fn into_phases(self) -> Vec<RawPhaseData> {
+        return self.phases;
+    }
+
+    /// Starts looking for the start of the phase with the given index, skipping
+    /// straight to its end if the start is already known
+    fn begin_phase(&mut self, idx: usize) {
+        let definition = match self.definitions.get(idx) {
+            None => {
+                self.state = ScanState::Finished;
+                return;
+            }
+            Some(definition) => definition,
+        };
+        debug!("Now looking for start of phase {}.", definition.phase_name);
+        match &definition.start_marker {
+            Some(boundary) if boundary.marker == PhaseMarker::FightStartMarker => {
+                match self.fight_start_event.clone() {
+                    Some(ev) => {
+                        let ev_time = ev.get_timestamp().unwrap_or(self.latest_time);
+                        self.start_phase(idx, ev, boundary.boundary_time(ev_time));
+                    }
+                    None => self.state = ScanState::Finished,
+                }
+            }
+            Some(boundary) => {
+                let matcher = MarkerMatcher::new(boundary, self.phase_start_time, self.latest_time);
+                self.state = ScanState::AwaitingStart(idx, matcher);
+            }
+            None => {
+                let mut phase: RawPhaseData = Default::default();
+                phase.phase_name = definition.phase_name.clone();
+                phase.phase_start = self.latest_time;
+                self.phase_start_time = phase.phase_start;
+                self.phases.push(phase);
+                self.begin_end(idx);
+            }
+        }
+    }
+
+    fn start_phase(&mut self, idx: usize, ev: ReportEvent, start_time: u64) {
+        let mut phase: RawPhaseData = Default::default();
+        phase.phase_name = self.definitions[idx].phase_name.clone();
+        phase.phase_start = start_time;
+        phase.phase_start_event = ev;
+        self.latest_time = phase.phase_start;
+        self.phase_start_time = phase.phase_start;
+        self.phases.push(phase);
+        self.begin_end(idx);
+    }
+
+    fn begin_end(&mut self, idx: usize) {
+        match &self.definitions[idx].end_marker {
+            Some(boundary) => {
+                let matcher = MarkerMatcher::new(boundary, self.phase_start_time, self.latest_time);
+                let next_start = self.next_start_matcher(idx);
+                self.state = ScanState::AwaitingEnd(idx, matcher, next_start);
+            }
+            None => self.begin_phase(idx + 1),
+        }
+    }
+
+    /// Creates a matcher for the start of the phase after the given one, if it starts
+    /// on an event
+    fn next_start_matcher(&self, idx: usize) -> Option<MarkerMatcher<'d>> {
+        let definitions = self.definitions;
+        let boundary = definitions.get(idx + 1)?.start_marker.as_ref()?;
+        if boundary.marker == PhaseMarker::FightStartMarker {
+            return None;
+        }
+        return Some(MarkerMatcher::new(
+            boundary,
+            self.phase_start_time,
+            self.latest_time,
+        ));
+    }
+
+    /// Passes an event to the marker currently being looked for. An event which
+    /// completes one marker is also offered to the next, as a single event may both
+    /// end one phase and start another.
+    fn feed(&mut self, ev: &ReportEvent) {
+        let ev_time = match ev.get_timestamp() {
+            Some(ts) => ts,
+            None => {
+                trace!("Skipping event without a timestamp: {:?}", ev);
+                return;
+            }
+        };
+        loop {
+            match &mut self.state {
+                ScanState::Finished => return,
+                ScanState::AwaitingStart(idx, matcher) => {
+                    //The phase can no longer start, so neither can any after it
+                    if matcher.window_closed(ev_time) {
+                        let name = &self.definitions[*idx].phase_name;
+                        debug!("Window for start of phase {} has closed.", name);
+                        self.state = ScanState::Finished;
+                        return;
+                    }
+                    if !matcher.feed(ev, ev_time) {
+                        return;
+                    }
+                    let idx = *idx;
+                    let start_time = matcher.boundary.boundary_time(ev_time);
+                    self.start_phase(idx, ev.clone(), start_time);
+                }
+                ScanState::AwaitingEnd(idx, matcher, next_start) => {
+                    //Leave the end of the phase to be filled in from the next phase's start
+                    if matcher.window_closed(ev_time) {
+                        let idx = *idx;
+                        let name = &self.definitions[idx].phase_name;
+                        debug!("Window for end of phase {} has closed.", name);
+                        match next_start.take() {
+                            Some(next_start) => {
+                                self.state = ScanState::AwaitingStart(idx + 1, next_start)
+                            }
+                            None => self.begin_phase(idx + 1),
+                        }
+                        continue;
+                    }
+                    if !matcher.feed(ev, ev_time) {
+                        let next_started = match next_start.as_mut() {
+                            Some(next_start) if !next_start.window_closed(ev_time) => {
+                                next_start.feed(ev, ev_time)
+                            }
+                            _ => false,
+                        };
+                        if !next_started {
+                            return;
+                        }
+                        //The next phase started before this one's end was found
+                        let idx = *idx;
+                        let name = &self.definitions[idx].phase_name;
+                        debug!("Phase {} ended without its end marker.", name);
+                        let boundary = next_start.as_ref().unwrap().boundary;
+                        self.start_phase(idx + 1, ev.clone(), boundary.boundary_time(ev_time));
+                        continue;
+                    }
+                    let idx = *idx;
+                    let end_time = matcher.boundary.boundary_time(ev_time);
+                    let phase = self.phases.last_mut().unwrap();
+                    phase.phase_end = Some(end_time);
+                    phase.phase_end_event = Some(ev.clone());
+                    self.latest_time = end_time;
+                    self.begin_phase(idx + 1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fflogs_api::report::events::ReportEventsList;
+    use crate::fflogs_api::report::fights::ReportFightsList;
+    use futures::future::BoxFuture;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Serves a fixed list of events for every request, recording the requests made
+    struct FightSource {
+        requests: Mutex<Vec<(EventsView, Option<String>)>>,
+    }
+
+    impl ReportDataSource for FightSource {
+        fn fetch_fights<'a>(
+            &'a self,
+            _: &'a str,
+            _: bool,
+        ) -> BoxFuture<'a, Result<ReportFightsList, ApiError>> {
+            return Box::pin(futures::future::ready(Err(ApiError::NotImplementedError)));
+        }
+
+        fn fetch_events<'a>(
+            &'a self,
+            view: EventsView,
+            _: &'a str,
+            filters: EventFilters,
+        ) -> BoxFuture<'a, Result<ReportEventsList, ApiError>> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((view, filters.filter.map(|f| f.to_string())));
+            let cast = |ts: u64, id: i64| {
+                json!({"timestamp": ts, "type": "begincast", "sourceID": 5, "sourceIsFriendly": false,
+                    "ability": {"name": "", "guid": id, "type": 1024, "abilityIcon": null}})
+            };
+            let events = match view {
+                EventsView::Summary => vec![json!({"timestamp": 1000, "type": "encounterstart",
+                    "encounterID": 1, "name": null, "difficulty": null, "size": null})],
+                _ => vec![cast(5000, 11102), cast(9000, 11102), cast(20000, 11152)],
+            };
+            let res = ReportEventsList {
+                events: events
+                    .into_iter()
+                    .map(|ev| serde_json::from_value(ev).unwrap())
+                    .collect(),
+                next_page_timestamp: None,
+            };
+            return Box::pin(futures::future::ready(Ok(res)));
+        }
+    }
+
+    #[test]
+    fn test_unfiltered_requests_are_not_combined() {
+        use crate::fflogs_api::report::filter::event_type;
+        let filtered = |ev_type: &str| EventFilters {
+            ability_id: Some(1),
+            filter: Some(event_type(ev_type)),
+            ..Default::default()
+        };
+        let unfiltered = EventFilters {
+            ability_id: Some(2),
+            ..Default::default()
+        };
+        let requests = combine_requests(
+            vec![
+                (EventsView::Casts, filtered("cast")),
+                (EventsView::Casts, unfiltered),
+                (EventsView::Casts, filtered("begincast")),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0].1.filter.as_ref().map(|f| f.to_string()),
+            Some(r#"type = "cast" OR type = "begincast""#.to_string())
+        );
+        assert_eq!(requests[1].1.ability_id, Some(2));
+    }
+
+    #[test]
+    fn test_detect_phases_in_one_scan() {
+        let definitions: toml::Value = toml::from_str(
+            r#"
+            [[phase]]
+            name = "Garuda"
+            startMarker = { type = "fightStart" }
+            [[phase]]
+            name = "Ifrit"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11102, instanceNo = 1 }
+            [[phase]]
+            name = "Titan"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11152 }
+            [[phase]]
+            name = "Lahabrea"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11122 }
+            "#,
+        )
+        .unwrap();
+        let definitions: Vec<PhaseDefinitionsPhase> =
+            definitions["phase"].clone().try_into().unwrap();
+        let source = FightSource {
+            requests: Mutex::new(Vec::new()),
+        };
+        let phases = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(detect_phases(
+                &definitions,
+                "abcd",
+                1000,
+                30000,
+                &Default::default(),
+                &source,
+            ))
+            .unwrap();
+        let starts: Vec<(String, u64)> = phases
+            .iter()
+            .map(|p| (p.phase_name.clone(), p.phase_start))
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                ("Garuda".to_string(), 1000),
+                ("Ifrit".to_string(), 9000),
+                ("Titan".to_string(), 20000)
+            ]
+        );
+        //One request for the fight start, and one for every cast marker
+        let requests = source.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1],
+            (
+                EventsView::Casts,
+                Some(
+                    r#"(type = "begincast" AND ability.id = 11102) OR (type = "begincast" AND ability.id = 11152) OR (type = "begincast" AND ability.id = 11122)"#
+                        .to_string()
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn test_targetable_marker_uses_game_id() {
+        let marker: PhaseMarker = toml::from_str(
+            r#"
+            type = "event"
+            evType = "Targetable"
+            actorId = 8346
+            targetable = false
+            "#,
+        )
+        .unwrap();
+        let enemies: Vec<Unit> = serde_json::from_value(json!([
+            {"name": "Living Liquid", "id": 14, "guid": 8346, "type": "Boss", "fights": []}
+        ]))
+        .unwrap();
+        let actors = create_actor_lookup(&enemies);
+        let update = |targetable: i64| -> ReportEvent {
+            serde_json::from_value(json!({"timestamp": 100, "type": "targetabilityupdate",
+                "sourceID": 14, "sourceIsFriendly": false, "targetable": targetable}))
+            .unwrap()
+        };
+
+        let mut ev = update(0);
+        assert!(!marker.check_event(&ev, &mut MarkerState::new(&marker)));
+        fill_actor_data(&mut ev, &actors);
+        assert!(marker.check_event(&ev, &mut MarkerState::new(&marker)));
+        let mut ev = update(1);
+        fill_actor_data(&mut ev, &actors);
+        assert!(!marker.check_event(&ev, &mut MarkerState::new(&marker)));
+    }
+
+    #[test]
+    fn test_marker_offsets_and_windows() {
+        let definitions: toml::Value = toml::from_str(
+            r#"
+            [[phase]]
+            name = "Garuda"
+            startMarker = { type = "fightStart" }
+            [[phase]]
+            name = "Ifrit"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11102, notBeforeMs = 6000, offsetMs = 500 }
+            [[phase]]
+            name = "Titan"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11152, notAfterMs = 5000 }
+            "#,
+        )
+        .unwrap();
+        let definitions: Vec<PhaseDefinitionsPhase> =
+            definitions["phase"].clone().try_into().unwrap();
+        let source = FightSource {
+            requests: Mutex::new(Vec::new()),
+        };
+        let phases = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(detect_phases(
+                &definitions,
+                "abcd",
+                1000,
+                30000,
+                &Default::default(),
+                &source,
+            ))
+            .unwrap();
+        //The first Ifrit cast is too soon after Garuda starts, and Titan's cast comes
+        //too long after Ifrit starts
+        let starts: Vec<(String, u64)> = phases
+            .iter()
+            .map(|p| (p.phase_name.clone(), p.phase_start))
+            .collect();
+        assert_eq!(
+            starts,
+            vec![("Garuda".to_string(), 1000), ("Ifrit".to_string(), 9500)]
+        );
+        assert_eq!(phases[1].phase_start_event.get_timestamp(), Some(9000));
+    }
+
+    #[test]
+    fn test_missing_end_does_not_stop_later_phases() {
+        let definitions: toml::Value = toml::from_str(
+            r#"
+            [[phase]]
+            name = "Garuda"
+            startMarker = { type = "fightStart" }
+            [[phase]]
+            name = "Ifrit"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11102 }
+            endMarker = { type = "event", evType = "BeginCast", abilityId = 11199 }
+            [[phase]]
+            name = "Titan"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11152 }
+            "#,
+        )
+        .unwrap();
+        let definitions: Vec<PhaseDefinitionsPhase> =
+            definitions["phase"].clone().try_into().unwrap();
+        let source = FightSource {
+            requests: Mutex::new(Vec::new()),
+        };
+        let phases = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(detect_phases(
+                &definitions,
+                "abcd",
+                1000,
+                30000,
+                &Default::default(),
+                &source,
+            ))
+            .unwrap();
+        //Ifrit's end never turns up, so it is left to be filled in from Titan's start
+        let times: Vec<(String, u64, Option<u64>)> = phases
+            .iter()
+            .map(|p| (p.phase_name.clone(), p.phase_start, p.phase_end))
+            .collect();
+        assert_eq!(
+            times,
+            vec![
+                ("Garuda".to_string(), 1000, None),
+                ("Ifrit".to_string(), 5000, None),
+                ("Titan".to_string(), 20000, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_inside_offset_gap_are_ignored() {
+        let definitions: toml::Value = toml::from_str(
+            r#"
+            [[phase]]
+            name = "Garuda"
+            startMarker = { type = "fightStart" }
+            [[phase]]
+            name = "Ifrit"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11102, offsetMs = 3000 }
+            endMarker = { type = "event", evType = "BeginCast", abilityId = 11102 }
+            [[phase]]
+            name = "Titan"
+            startMarker = { type = "event", evType = "BeginCast", abilityId = 11152 }
+            "#,
+        )
+        .unwrap();
+        let definitions: Vec<PhaseDefinitionsPhase> =
+            definitions["phase"].clone().try_into().unwrap();
+        let source = FightSource {
+            requests: Mutex::new(Vec::new()),
+        };
+        let phases = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(detect_phases(
+                &definitions,
+                "abcd",
+                1000,
+                30000,
+                &Default::default(),
+                &source,
+            ))
+            .unwrap();
+        //The cast at 5000 starts Ifrit at 8000, so can't also end it
+        let times: Vec<(u64, Option<u64>)> = phases
+            .iter()
+            .map(|p| (p.phase_start, p.phase_end))
+            .collect();
+        assert_eq!(times, vec![(1000, None), (8000, Some(9000)), (20000, None)]);
+    }
+}
@@ -1,5 +1,6 @@
 use dotenv;
 use std::env;
+use std::sync::Arc;
 
 use clap::{App, Arg};
 
@@ -9,15 +10,40 @@ pub mod discord_bot;
 pub mod fflogs_api;
 pub mod fight_analysis;
 
+use fflogs_api::api::ApiClientConfig;
+use fflogs_api::cache::CacheConfig;
+use fflogs_api::fixtures::{FixtureConfig, FixtureMode};
+use fflogs_api::v2::client::new_fflogs_v2_client;
+use fight_analysis::analyse_fight::LogAnalysisClient;
+
 const DEFAULT_PI_DIR: &'static str = "./phaseidentifiers";
 
+/// Credentials for whichever version of the FFLogs API should be used
+enum FFLogsCredentials {
+    V1ApiKey(String),
+    V2Client {
+        client_id: String,
+        client_secret: String,
+    },
+}
+
 pub fn start() {
     //Load config options
-    let (discord_api_key, fflogs_api_key, definitions_dir) = load_options();
+    let (discord_api_key, fflogs_credentials, definitions_dir, api_config) = load_options();
     //Create client for analysis
-    let analysis_client =
-        fight_analysis::analyse_fight::LogAnalysisClient::new(&fflogs_api_key, &definitions_dir)
-            .unwrap();
+    let analysis_client = match fflogs_credentials {
+        FFLogsCredentials::V1ApiKey(key) => {
+            LogAnalysisClient::new_with_config(&key, &definitions_dir, api_config)
+        }
+        FFLogsCredentials::V2Client {
+            client_id,
+            client_secret,
+        } => LogAnalysisClient::with_source(
+            Arc::new(new_fflogs_v2_client(&client_id, &client_secret, api_config)),
+            &definitions_dir,
+        ),
+    }
+    .unwrap();
     //Start bot
     let bot_future = discord_bot::start_bot::start_bot(discord_api_key, analysis_client);
 
@@ -26,7 +52,7 @@ pub fn start() {
         .block_on(bot_future);
 }
 
-fn load_options() -> (String, String, String) {
+fn load_options() -> (String, FFLogsCredentials, String, ApiClientConfig) {
     //Parse cli arguments
     let matches = App::new("Kusanagi discord bot")
         .version("0.2")
@@ -47,6 +73,13 @@ fn load_options() -> (String, String, String) {
                 .help("Path to file containing FFLogs API token")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("fflogs_client_file")
+                .long("fflogs_client_file")
+                .value_name("FILE")
+                .help("Path to file containing FFLogs v2 client ID and secret, one per line")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("phase_identifiers_dir")
                 .short("p")
@@ -55,6 +88,29 @@ fn load_options() -> (String, String, String) {
                 .help("Path to directory containing phase definitions")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("cache_dir")
+                .short("c")
+                .long("cache_dir")
+                .value_name("PATH")
+                .help("Path to directory in which FFLogs responses should be cached")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("record_fixtures")
+                .long("record_fixtures")
+                .value_name("PATH")
+                .help("Record all FFLogs API traffic to the given fixture directory")
+                .takes_value(true)
+                .conflicts_with("replay_fixtures"),
+        )
+        .arg(
+            Arg::with_name("replay_fixtures")
+                .long("replay_fixtures")
+                .value_name("PATH")
+                .help("Serve FFLogs API responses from the given fixture directory")
+                .takes_value(true),
+        )
         .get_matches();
     //Load environment variables
     dotenv::dotenv().ok();
@@ -65,18 +121,78 @@ fn load_options() -> (String, String, String) {
         .map(|path| std::fs::read_to_string(path).expect("Could not read Discord token file."))
         .or(env::var("discord_api_key").ok())
         .expect("Could not find Discord API key.");
-    let fflogs_api_key: String = matches
-        .value_of("fflogs_token_file")
-        .map(|val| val.to_string())
-        .or(env::var("fflogs_api_key_file").ok())
-        .map(|path| std::fs::read_to_string(path).expect("Could not read fflogs API key file."))
-        .or(env::var("fflogs_api_key").ok())
-        .expect("Could not find FFLogs API key.");
+    //Prefer v2 client credentials if we have them, falling back to a v1 API key
+    let fflogs_v2_client: Option<(String, String)> = matches
+        .value_of("fflogs_client_file")
+        .map(|path| {
+            let contents = std::fs::read_to_string(path)
+                .expect("Could not read FFLogs client credentials file.");
+            let mut lines = contents.lines().map(|line| line.trim().to_string());
+            let id = lines
+                .next()
+                .expect("FFLogs client file is missing client ID.");
+            let secret = lines
+                .next()
+                .expect("FFLogs client file is missing client secret.");
+            (id, secret)
+        })
+        .or(env::var("fflogs_client_id")
+            .ok()
+            .and_then(|id| env::var("fflogs_client_secret").ok().map(|s| (id, s))));
+    let fflogs_credentials = match fflogs_v2_client {
+        Some((id, secret)) => FFLogsCredentials::V2Client {
+            client_id: id,
+            client_secret: secret,
+        },
+        None => FFLogsCredentials::V1ApiKey(
+            matches
+                .value_of("fflogs_token_file")
+                .map(|val| val.to_string())
+                .or(env::var("fflogs_api_key_file").ok())
+                .map(|path| {
+                    std::fs::read_to_string(path).expect("Could not read fflogs API key file.")
+                })
+                .or(env::var("fflogs_api_key").ok())
+                .expect("Could not find FFLogs API key or v2 client credentials."),
+        ),
+    };
     let phase_definitions_dir: String = matches
         .value_of("phase_identifiers_dir")
         .map(|val| val.to_string())
         .or(env::var("phaseidentifiers_dir").ok())
         .unwrap_or(DEFAULT_PI_DIR.to_string());
+    let cache_dir: Option<String> = matches
+        .value_of("cache_dir")
+        .map(|val| val.to_string())
+        .or(env::var("fflogs_cache_dir").ok());
+    let fixtures: Option<FixtureConfig> = matches
+        .value_of("record_fixtures")
+        .map(|dir| (FixtureMode::Record, dir))
+        .or(matches
+            .value_of("replay_fixtures")
+            .map(|dir| (FixtureMode::Replay, dir)))
+        .map(|(mode, dir)| FixtureConfig {
+            mode: mode,
+            directory: dir.into(),
+        });
+
+    let mut api_config: ApiClientConfig = Default::default();
+    api_config.cache = cache_dir.map(|dir| CacheConfig::new(&dir));
+    api_config.fixtures = fixtures;
+    //Allow the API server to be overridden, e.g. to point at a local mock server
+    if let Ok(base_url) = env::var("fflogs_api_url") {
+        let uri: http::Uri = base_url.parse().expect("Could not parse FFLogs API url.");
+        api_config.scheme = uri.scheme_str().unwrap_or("https").to_string();
+        api_config.authority = uri
+            .authority()
+            .expect("FFLogs API url must contain a host.")
+            .to_string();
+    }
 
-    return (discord_token, fflogs_api_key, phase_definitions_dir);
+    return (
+        discord_token,
+        fflogs_credentials,
+        phase_definitions_dir,
+        api_config,
+    );
 }